@@ -12,10 +12,74 @@ impl Default for MQTTConfig {
     }
 }
 
+/// How OCPP frames are carried to the central system
+pub enum TransportKind {
+    Mqtt,
+    WebSocket,
+}
+
+pub struct WebSocketConfig {
+    /// ws:// or wss:// url of the central system, the charge point id is appended
+    pub url: String,
+    /// seconds between pings
+    pub ping_interval: u64,
+    /// seconds to wait before reconnecting
+    pub reconnect_timeout: u64,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            url: "".into(),
+            ping_interval: 30,
+            reconnect_timeout: 10,
+        }
+    }
+}
+
+/// Defaults of the OCPP configuration keys, the central system can change them with ChangeConfiguration
+pub struct OCPPConfig {
+    /// seconds to wait for a CallResult/CallError before a Call is considered lost
+    pub response_timeout: u64,
+    /// seconds between heartbeats and BootNotification retries until the central system sends an interval
+    pub heartbeat_interval: u64,
+    /// start right away for idTags Accepted in the local authorization list or authorization cache
+    pub local_pre_authorize: bool,
+    /// use the local authorization list and authorization cache while the central system can't be reached
+    pub local_authorize_offline: bool,
+    /// remember the IdTagInfo of presented idTags in the authorization cache
+    pub authorization_cache_enabled: bool,
+    /// seconds between MeterValues during a transaction
+    pub meter_value_sample_interval: u64,
+}
+
+impl Default for OCPPConfig {
+    fn default() -> Self {
+        Self {
+            response_timeout: 30,
+            heartbeat_interval: 60,
+            local_pre_authorize: false,
+            local_authorize_offline: true,
+            authorization_cache_enabled: true,
+            meter_value_sample_interval: 60,
+        }
+    }
+}
+
 pub struct ChargerConfig {
     pub serial: String,
     pub vendor: String,
     pub model: String,
+    /// number of phases the meter reports Current.Import for
+    pub phases: usize,
+    /// rating of the connector in A, the limit when no charging profile applies
+    pub max_current: f64,
+    /// nominal voltage between phase and neutral, to convert charging limits in W to A
+    pub voltage: f64,
+    /// the socket has a cable lock actuator, false for a fixed cable
+    pub cable_lock: bool,
+    /// spill the log lines that no longer fit in RAM to flash, for GetDiagnostics
+    pub log_to_flash: bool,
 }
 
 impl Default for ChargerConfig {
@@ -24,6 +88,11 @@ impl Default for ChargerConfig {
             serial: "".into(),
             model: "".into(),
             vendor: "".into(),
+            phases: 3,
+            max_current: 16.0,
+            voltage: 230.0,
+            cable_lock: true,
+            log_to_flash: true,
         }
     }
 }
@@ -31,8 +100,11 @@ impl Default for ChargerConfig {
 pub struct Config {
     pub ssid: String,
     pub password: String,
+    pub transport: TransportKind,
     pub mqtt: MQTTConfig,
+    pub websocket: WebSocketConfig,
     pub charger: ChargerConfig,
+    pub ocpp: OCPPConfig,
 }
 
 impl Default for Config {
@@ -40,8 +112,11 @@ impl Default for Config {
         Self {
             ssid: "".into(),
             password: "".into(),
+            transport: TransportKind::Mqtt,
             mqtt: MQTTConfig::default(),
+            websocket: WebSocketConfig::default(),
             charger: ChargerConfig::default(),
+            ocpp: OCPPConfig::default(),
         }
     }
 }
@@ -0,0 +1,153 @@
+use rust_ocpp::v1_6::types::IdTagInfo;
+
+use crate::local_list;
+use crate::storage::Storage;
+
+/// Maximum number of idTags kept in the cache, the least recently used one is evicted first
+pub const MAX_ENTRIES: usize = 50;
+
+const KEY: &str = "auth_cache";
+
+/// AuthorizationCache
+/// The IdTagInfo of recently presented idTags as returned by the central system
+pub struct AuthorizationCache {
+    /// least recently used first
+    entries: Vec<(String, IdTagInfo)>,
+    storage: Box<dyn Storage>,
+}
+
+impl AuthorizationCache {
+    /// Loads the cache persisted in `storage`, an empty cache when there is none or it can't be read
+    pub fn load(storage: Box<dyn Storage>) -> Self {
+        let entries = match storage.load(KEY) {
+            Ok(Some(data)) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::error!("Discarding unreadable authorization cache: {}", e);
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                log::error!("Failed to load the authorization cache: {}", e);
+                Vec::new()
+            }
+        };
+        Self { entries, storage }
+    }
+
+    /// The IdTagInfo of `id_tag`, with status Expired once its expiry date has passed
+    pub fn get(&mut self, id_tag: &str) -> Option<IdTagInfo> {
+        let index = self.entries.iter().position(|(tag, _)| tag == id_tag)?;
+        let entry = self.entries.remove(index);
+        let info = local_list::expire(entry.1.clone());
+        self.entries.push(entry);
+        Some(info)
+    }
+
+    /// Records the IdTagInfo the central system returned for `id_tag`
+    pub fn update(&mut self, id_tag: &str, info: IdTagInfo) {
+        self.entries.retain(|(tag, _)| tag != id_tag);
+        self.entries.push((id_tag.to_string(), info));
+        if self.entries.len() > MAX_ENTRIES {
+            let evicted = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..evicted);
+        }
+        if let Err(e) = self.persist() {
+            log::error!("Failed to store the authorization cache: {}", e);
+        }
+    }
+
+    /// Removes all entries, for a ClearCache
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.storage.remove(KEY)?;
+        self.entries.clear();
+        Ok(())
+    }
+
+    fn persist(&mut self) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(&self.entries)?;
+        self.storage.store(KEY, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rust_ocpp::v1_6::types::AuthorizationStatus;
+
+    use super::*;
+    use crate::clock;
+    use crate::storage::memory::MemoryStorage;
+
+    fn info(status: AuthorizationStatus) -> IdTagInfo {
+        IdTagInfo {
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn status(cache: &mut AuthorizationCache, id_tag: &str) -> Option<AuthorizationStatus> {
+        cache.get(id_tag).map(|info| info.status)
+    }
+
+    #[test]
+    fn the_least_recently_used_entry_is_evicted() {
+        let mut cache = AuthorizationCache::load(Box::new(MemoryStorage::new()));
+        for i in 0..MAX_ENTRIES {
+            cache.update(&i.to_string(), info(AuthorizationStatus::Accepted));
+        }
+        // using "0" makes "1" the least recently used
+        assert_eq!(status(&mut cache, "0"), Some(AuthorizationStatus::Accepted));
+        cache.update("new", info(AuthorizationStatus::Blocked));
+        assert_eq!(status(&mut cache, "1"), None);
+        assert_eq!(status(&mut cache, "0"), Some(AuthorizationStatus::Accepted));
+        assert_eq!(
+            status(&mut cache, "new"),
+            Some(AuthorizationStatus::Blocked)
+        );
+
+        // updating an entry doesn't take another place
+        cache.update("new", info(AuthorizationStatus::Accepted));
+        assert_eq!(status(&mut cache, "2"), Some(AuthorizationStatus::Accepted));
+        assert_eq!(
+            status(&mut cache, "new"),
+            Some(AuthorizationStatus::Accepted)
+        );
+    }
+
+    #[test]
+    fn entries_expire() {
+        clock::set_from_sntp();
+        let mut cache = AuthorizationCache::load(Box::new(MemoryStorage::new()));
+        let with_expiry = |hours| IdTagInfo {
+            expiry_date: Some(clock::now() + Duration::hours(hours)),
+            ..info(AuthorizationStatus::Accepted)
+        };
+        cache.update("expired", with_expiry(-1));
+        cache.update("valid", with_expiry(1));
+        assert_eq!(
+            status(&mut cache, "expired"),
+            Some(AuthorizationStatus::Expired)
+        );
+        assert_eq!(
+            status(&mut cache, "valid"),
+            Some(AuthorizationStatus::Accepted)
+        );
+    }
+
+    #[test]
+    fn the_cache_is_persisted_until_cleared() {
+        let storage = MemoryStorage::new();
+        let mut cache = AuthorizationCache::load(Box::new(storage.clone()));
+        cache.update("a", info(AuthorizationStatus::Accepted));
+        cache.update("b", info(AuthorizationStatus::Invalid));
+
+        let mut cache = AuthorizationCache::load(Box::new(storage.clone()));
+        assert_eq!(status(&mut cache, "a"), Some(AuthorizationStatus::Accepted));
+        assert_eq!(status(&mut cache, "b"), Some(AuthorizationStatus::Invalid));
+
+        cache.clear().unwrap();
+        assert_eq!(status(&mut cache, "a"), None);
+        let mut cache = AuthorizationCache::load(Box::new(storage));
+        assert_eq!(status(&mut cache, "a"), None);
+        assert_eq!(status(&mut cache, "b"), None);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeSet;
+
+use rust_ocpp::v1_6::types::{AvailabilityStatus, AvailabilityType};
+
+use crate::charger::{Charger, ChargerInput, State};
+use crate::storage::Storage;
+
+const KEY: &str = "inoperative";
+
+/// Availability
+/// The connectors taken out of service with ChangeAvailability, connector 0 is the charger as a whole
+pub struct Availability {
+    inoperative: BTreeSet<u64>,
+    storage: Box<dyn Storage>,
+}
+
+impl Availability {
+    /// Loads the availability persisted in `storage`, everything is operative when there is none
+    pub fn load(storage: Box<dyn Storage>) -> Self {
+        let inoperative = match storage.load(KEY) {
+            Ok(Some(data)) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::error!("Discarding unreadable availability: {}", e);
+                BTreeSet::new()
+            }),
+            Ok(None) => BTreeSet::new(),
+            Err(e) => {
+                log::error!("Failed to load the availability: {}", e);
+                BTreeSet::new()
+            }
+        };
+        Self {
+            inoperative,
+            storage,
+        }
+    }
+
+    /// Whether a connector may be used, it isn't when either it or the charger is inoperative
+    pub fn is_operative(&self, connector_id: u64) -> bool {
+        !self.inoperative.contains(&0) && !self.inoperative.contains(&connector_id)
+    }
+
+    /// Whether `connector_id` would be operative once `changed` is set to `kind`, without
+    /// recording the change
+    pub fn is_operative_after(
+        &self,
+        changed: u64,
+        kind: &AvailabilityType,
+        connector_id: u64,
+    ) -> bool {
+        let inoperative = self.changed(changed, kind);
+        !inoperative.contains(&0) && !inoperative.contains(&connector_id)
+    }
+
+    /// Records and persists the availability of a connector, or of the charger for connector 0
+    pub fn change(&mut self, connector_id: u64, kind: AvailabilityType) -> anyhow::Result<()> {
+        let inoperative = self.changed(connector_id, &kind);
+        self.storage
+            .store(KEY, &serde_json::to_vec(&inoperative)?)?;
+        self.inoperative = inoperative;
+        Ok(())
+    }
+
+    fn changed(&self, connector_id: u64, kind: &AvailabilityType) -> BTreeSet<u64> {
+        let mut inoperative = self.inoperative.clone();
+        match kind {
+            AvailabilityType::Operative => inoperative.remove(&connector_id),
+            AvailabilityType::Inoperative => inoperative.insert(connector_id),
+        };
+        inoperative
+    }
+}
+
+/// Takes the connector of `charger` in or out of service for a ChangeAvailability
+///
+/// # Arguments
+///
+/// * `charger` - the charger the connector belongs to
+/// * `operative` - whether the connector is to be operative
+///
+/// # Returns
+///
+/// AvailabilityStatus - Scheduled while a transaction, a reservation or an error holds the
+/// connector, Rejected when the state machine refuses the change
+///
+pub fn apply(charger: &mut Charger, operative: bool) -> AvailabilityStatus {
+    let state = charger.get_state();
+    if operative == (state != State::Unavailable) {
+        return AvailabilityStatus::Accepted;
+    }
+    if matches!(state, State::Charging | State::Reserved | State::Error) {
+        // the report thread takes the connector out of service when the transaction or the
+        // reservation ends or the error clears
+        return AvailabilityStatus::Scheduled;
+    }
+    let input = if operative {
+        ChargerInput::Operative
+    } else {
+        ChargerInput::Inoperative
+    };
+    match charger.transition(input) {
+        Ok(_) => AvailabilityStatus::Accepted,
+        Err(_) => AvailabilityStatus::Rejected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::charger::ChargerId;
+    use crate::evse::Evse;
+    use crate::storage::memory::MemoryStorage;
+
+    fn charger(state: State) -> Charger {
+        Charger::new(ChargerId::new(), state, vec![Evse::default()])
+    }
+
+    #[test]
+    fn a_charging_connector_is_scheduled() {
+        let mut charger = charger(State::Charging);
+        assert_eq!(apply(&mut charger, false), AvailabilityStatus::Scheduled);
+        assert_eq!(charger.get_state(), State::Charging);
+        // already operative
+        assert_eq!(apply(&mut charger, true), AvailabilityStatus::Accepted);
+    }
+
+    #[test]
+    fn an_idle_connector_is_accepted() {
+        let mut charger = charger(State::Available);
+        assert_eq!(apply(&mut charger, false), AvailabilityStatus::Accepted);
+        assert_eq!(charger.get_state(), State::Unavailable);
+        assert_eq!(apply(&mut charger, false), AvailabilityStatus::Accepted);
+
+        charger.cable_connected = true;
+        assert_eq!(apply(&mut charger, true), AvailabilityStatus::Accepted);
+        assert_eq!(charger.get_state(), State::Occupied);
+    }
+
+    #[test]
+    fn the_charger_covers_its_connectors() {
+        let storage = MemoryStorage::new();
+        let mut availability = Availability::load(Box::new(storage.clone()));
+        assert!(availability.is_operative(1));
+        assert!(!availability.is_operative_after(0, &AvailabilityType::Inoperative, 1));
+        // nothing recorded yet
+        assert!(availability.is_operative(1));
+
+        availability
+            .change(0, AvailabilityType::Inoperative)
+            .unwrap();
+        assert!(!availability.is_operative(0));
+        assert!(!availability.is_operative(1));
+        assert!(!availability.is_operative_after(1, &AvailabilityType::Operative, 1));
+
+        let availability = Availability::load(Box::new(storage));
+        assert!(!availability.is_operative(1));
+        assert!(availability.is_operative_after(0, &AvailabilityType::Operative, 1));
+    }
+}
@@ -0,0 +1,14 @@
+#[cfg(target_os = "espidf")]
+pub mod gpio;
+
+/// CableLock
+/// The actuator that locks the cable in the socket of a connector
+pub trait CableLock: Send {
+    /// Locks the cable, fails when the feedback doesn't confirm it is locked
+    fn lock(&mut self) -> anyhow::Result<()>;
+
+    /// Unlocks the cable, fails when the feedback still reports it locked
+    fn unlock(&mut self) -> anyhow::Result<()>;
+
+    fn is_locked(&self) -> bool;
+}
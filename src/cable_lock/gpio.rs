@@ -0,0 +1,52 @@
+use std::thread;
+use std::time::Duration;
+
+use esp_idf_svc::hal::gpio::{AnyInputPin, AnyOutputPin, Input, Output, PinDriver, Pull};
+
+use super::CableLock;
+
+/// Time the actuator needs to move the locking pin
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// GpioCableLock
+/// A lock actuator driven by an output pin (high is locked) with a feedback switch on an input pin
+/// that pulls it low while the cable is locked
+pub struct GpioCableLock {
+    actuator: PinDriver<'static, AnyOutputPin, Output>,
+    feedback: PinDriver<'static, AnyInputPin, Input>,
+}
+
+impl GpioCableLock {
+    pub fn new(actuator: AnyOutputPin, feedback: AnyInputPin) -> anyhow::Result<Self> {
+        let mut actuator = PinDriver::output(actuator)?;
+        actuator.set_low()?;
+        let mut feedback = PinDriver::input(feedback)?;
+        feedback.set_pull(Pull::Up)?;
+        Ok(Self { actuator, feedback })
+    }
+}
+
+impl CableLock for GpioCableLock {
+    fn lock(&mut self) -> anyhow::Result<()> {
+        self.actuator.set_high()?;
+        thread::sleep(SETTLE_TIME);
+        if !self.is_locked() {
+            self.actuator.set_low()?;
+            anyhow::bail!("Cable lock did not engage");
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> anyhow::Result<()> {
+        self.actuator.set_low()?;
+        thread::sleep(SETTLE_TIME);
+        if self.is_locked() {
+            anyhow::bail!("Cable is still locked");
+        }
+        Ok(())
+    }
+
+    fn is_locked(&self) -> bool {
+        self.feedback.is_low()
+    }
+}
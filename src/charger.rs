@@ -1,4 +1,4 @@
-use crate::evse::Evse;
+use crate::evse::{Evse, Reservation};
 use uuid::Uuid;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -29,8 +29,13 @@ impl Default for ChargerId {
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum State {
     Available,
+    /// held for an idTag with ReserveNow
+    Reserved,
     Occupied,
+    Authorizing,
     Charging,
+    /// taken out of service with ChangeAvailability
+    Unavailable,
     Error,
     Off,
 }
@@ -39,8 +44,11 @@ impl State {
     pub fn as_str(&self) -> &str {
         match self {
             State::Available => "available",
+            State::Reserved => "reserved",
             State::Occupied => "occupied",
+            State::Authorizing => "authorizing",
             State::Charging => "charging",
+            State::Unavailable => "unavailable",
             State::Error => "error",
             State::Off => "off",
         }
@@ -54,6 +62,14 @@ pub enum ChargerInput {
     PlugIn,
     PlugOut,
     Swipe,
+    RemoteStart,
+    RemoteStop,
+    Authorized,
+    Unauthorized,
+    Operative,
+    Inoperative,
+    Reserve,
+    CancelReservation,
 }
 impl ChargerInput {
     fn as_str(&self) -> &str {
@@ -61,6 +77,14 @@ impl ChargerInput {
             ChargerInput::PlugIn => "PlugIn",
             ChargerInput::PlugOut => "PlugOut",
             ChargerInput::Swipe => "Swipe",
+            ChargerInput::RemoteStart => "RemoteStart",
+            ChargerInput::RemoteStop => "RemoteStop",
+            ChargerInput::Authorized => "Authorized",
+            ChargerInput::Unauthorized => "Unauthorized",
+            ChargerInput::Operative => "Operative",
+            ChargerInput::Inoperative => "Inoperative",
+            ChargerInput::Reserve => "Reserve",
+            ChargerInput::CancelReservation => "CancelReservation",
         }
     }
 }
@@ -70,21 +94,59 @@ impl ChargerInput {
 #[derive(Debug)]
 pub enum ChargerOutput {
     Unlocked,
+    AwaitingAuthorization,
     LockedAndPowerIsOn,
     Errored,
 }
 
 /// Charger
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct Charger {
     pub id: ChargerId,
     pub state: State,
     pub evses: Vec<Evse>,
+    /// whether a cable is plugged in, to return to Occupied when made operative again
+    pub cable_connected: bool,
+    /// whether the connector became Occupied because its transaction ended, until the next state change
+    pub finishing: bool,
 }
 
 impl Charger {
     pub fn new(id: ChargerId, state: State, evses: Vec<Evse>) -> Self {
-        Self { id, state, evses }
+        Self {
+            id,
+            state,
+            evses,
+            cable_connected: false,
+            finishing: false,
+        }
+    }
+
+    /// The EVSE of a connector, connector ids start at 1
+    pub fn evse_mut(&mut self, connector_id: u64) -> Option<&mut Evse> {
+        let index = connector_id.checked_sub(1)?;
+        self.evses.get_mut(index as usize)
+    }
+
+    /// The reservation of the connector
+    pub fn reservation(&self) -> Option<&Reservation> {
+        self.evses
+            .first()
+            .and_then(|evse| evse.reservation.as_ref())
+    }
+
+    /// Whether the connector is held for a reservation
+    pub fn is_reserved(&self) -> bool {
+        self.reservation().is_some()
+    }
+
+    /// Drops the reservation of the connector, a Reserved connector becomes available again
+    pub fn cancel_reservation(&mut self) -> Option<Reservation> {
+        let reservation = self.evse_mut(1)?.reservation.take()?;
+        if self.state == State::Reserved {
+            let _ = self.transition(ChargerInput::CancelReservation);
+        }
+        Some(reservation)
     }
 
     pub fn get_state(&self) -> State {
@@ -92,6 +154,8 @@ impl Charger {
     }
 
     pub fn set_state(&mut self, state: State) -> State {
+        self.finishing = state == State::Occupied
+            && (self.state == State::Charging || (self.finishing && self.state == State::Occupied));
         self.state = state;
         self.state.clone()
     }
@@ -100,8 +164,11 @@ impl Charger {
         match action {
             "error" => self.set_state(State::Error),
             "available" => self.set_state(State::Available),
+            "reserved" => self.set_state(State::Reserved),
             "occupied" => self.set_state(State::Occupied),
+            "authorizing" => self.set_state(State::Authorizing),
             "charging" => self.set_state(State::Charging),
+            "unavailable" => self.set_state(State::Unavailable),
             "off" => self.set_state(State::Off),
             _ => self.set_state(State::Error),
         };
@@ -119,6 +186,11 @@ impl Charger {
     ///
     pub fn transition(&mut self, input: ChargerInput) -> Result<(State, ChargerOutput)> {
         let orginal_state = self.state.clone();
+        match input {
+            ChargerInput::PlugIn => self.cable_connected = true,
+            ChargerInput::PlugOut => self.cable_connected = false,
+            _ => {}
+        }
 
         let output = match (input, self.state.clone()) {
             (ChargerInput::PlugIn, State::Available) => {
@@ -128,13 +200,78 @@ impl Charger {
                 Ok((self.set_state(State::Available), ChargerOutput::Unlocked))
             }
             (ChargerInput::Swipe, State::Occupied) => Ok((
+                self.set_state(State::Authorizing),
+                ChargerOutput::AwaitingAuthorization,
+            )),
+            (ChargerInput::Authorized, State::Authorizing) => Ok((
                 self.set_state(State::Charging),
                 ChargerOutput::LockedAndPowerIsOn,
             )),
+            (ChargerInput::Unauthorized, State::Authorizing | State::Charging) => {
+                let state = if self.is_reserved() {
+                    State::Reserved
+                } else {
+                    State::Occupied
+                };
+                Ok((self.set_state(state), ChargerOutput::Unlocked))
+            }
+            (ChargerInput::PlugOut, State::Authorizing) => {
+                let state = if self.is_reserved() {
+                    State::Reserved
+                } else {
+                    State::Available
+                };
+                Ok((self.set_state(state), ChargerOutput::Unlocked))
+            }
             (ChargerInput::Swipe, State::Charging) => {
                 Ok((self.set_state(State::Occupied), ChargerOutput::Unlocked))
             }
+            (ChargerInput::RemoteStart, State::Occupied) => Ok((
+                self.set_state(State::Charging),
+                ChargerOutput::LockedAndPowerIsOn,
+            )),
+            (ChargerInput::Reserve, State::Available) => {
+                Ok((self.set_state(State::Reserved), ChargerOutput::Unlocked))
+            }
+            (ChargerInput::CancelReservation, State::Reserved) => {
+                let state = if self.cable_connected {
+                    State::Occupied
+                } else {
+                    State::Available
+                };
+                Ok((self.set_state(state), ChargerOutput::Unlocked))
+            }
+            (ChargerInput::PlugIn | ChargerInput::PlugOut, State::Reserved) => {
+                Ok((State::Reserved, ChargerOutput::Unlocked))
+            }
+            // only with a cable, whether the idTag may use the reservation is checked on authorization
+            (ChargerInput::Swipe, State::Reserved) if self.cable_connected => Ok((
+                self.set_state(State::Authorizing),
+                ChargerOutput::AwaitingAuthorization,
+            )),
+            (ChargerInput::RemoteStart, State::Reserved) if self.cable_connected => Ok((
+                self.set_state(State::Charging),
+                ChargerOutput::LockedAndPowerIsOn,
+            )),
+            (ChargerInput::RemoteStop, State::Charging) => {
+                Ok((self.set_state(State::Occupied), ChargerOutput::Unlocked))
+            }
             (ChargerInput::PlugOut, State::Charging) => Err("Cannot unplug while charging".into()),
+            (
+                ChargerInput::Inoperative,
+                State::Available | State::Occupied | State::Authorizing,
+            ) => Ok((self.set_state(State::Unavailable), ChargerOutput::Unlocked)),
+            (ChargerInput::Operative, State::Unavailable) => {
+                let state = if self.cable_connected {
+                    State::Occupied
+                } else {
+                    State::Available
+                };
+                Ok((self.set_state(state), ChargerOutput::Unlocked))
+            }
+            (ChargerInput::PlugIn | ChargerInput::PlugOut, State::Unavailable) => {
+                Ok((State::Unavailable, ChargerOutput::Unlocked))
+            }
             (_, State::Error) => Ok((self.set_state(State::Error), ChargerOutput::Errored)),
             _ => {
                 log::warn!(
@@ -162,6 +299,30 @@ impl Default for Charger {
             id: ChargerId::new(),
             state: State::Off,
             evses: vec![Evse::default()],
+            cable_connected: false,
+            finishing: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finishing_after_the_transaction_ends() {
+        let mut charger = Charger::new(ChargerId::new(), State::Available, vec![Evse::default()]);
+        charger.transition(ChargerInput::PlugIn).unwrap();
+        assert!(!charger.finishing);
+        charger.transition(ChargerInput::RemoteStart).unwrap();
+        charger.transition(ChargerInput::RemoteStop).unwrap();
+        assert_eq!(charger.get_state(), State::Occupied);
+        assert!(charger.finishing);
+
+        charger.transition(ChargerInput::Swipe).unwrap();
+        assert!(!charger.finishing);
+        charger.transition(ChargerInput::Unauthorized).unwrap();
+        assert_eq!(charger.get_state(), State::Occupied);
+        assert!(!charger.finishing);
+    }
+}
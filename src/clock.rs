@@ -0,0 +1,86 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// TimeSource
+/// Where the time of the clock came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSource {
+    Unsynchronized,
+    CentralSystem,
+    Sntp,
+}
+
+struct State {
+    /// correction in milliseconds for targets where the system time can't be set
+    offset: i64,
+    source: TimeSource,
+}
+
+/// The device has no RTC, the system time starts at 1970 after every boot until it is synchronized
+static CLOCK: Mutex<State> = Mutex::new(State {
+    offset: 0,
+    source: TimeSource::Unsynchronized,
+});
+
+/// The current time, use this instead of `Utc::now()` for timestamps sent to the central system
+pub fn now() -> DateTime<Utc> {
+    Utc::now() + Duration::milliseconds(CLOCK.lock().unwrap().offset)
+}
+
+pub fn source() -> TimeSource {
+    CLOCK.lock().unwrap().source
+}
+
+/// Whether the time has been synchronized since boot
+pub fn is_trusted() -> bool {
+    source() != TimeSource::Unsynchronized
+}
+
+/// Records that SNTP has set the system time, from now on the central system's time is ignored
+pub fn set_from_sntp() {
+    let mut clock = CLOCK.lock().unwrap();
+    if clock.source != TimeSource::Sntp {
+        log::info!("Time synchronized by SNTP: {}", Utc::now());
+    }
+    clock.offset = 0;
+    clock.source = TimeSource::Sntp;
+}
+
+/// Sets the time from the `currentTime` of a BootNotification or Heartbeat response, unless SNTP is synchronized
+pub fn set_from_central_system(time: DateTime<Utc>) {
+    let mut clock = CLOCK.lock().unwrap();
+    if clock.source == TimeSource::Sntp {
+        return;
+    }
+    clock.offset = match set_system_time(time) {
+        Ok(()) => 0,
+        Err(e) => {
+            log::warn!("Failed to set the system time, keeping an offset: {}", e);
+            (time - Utc::now()).num_milliseconds()
+        }
+    };
+    if clock.source == TimeSource::Unsynchronized {
+        log::info!("Time synchronized by the central system: {}", time);
+    }
+    clock.source = TimeSource::CentralSystem;
+}
+
+#[cfg(target_os = "espidf")]
+fn set_system_time(time: DateTime<Utc>) -> anyhow::Result<()> {
+    use esp_idf_svc::sys::{settimeofday, timeval};
+
+    let tv = timeval {
+        tv_sec: time.timestamp() as _,
+        tv_usec: time.timestamp_subsec_micros() as _,
+    };
+    if unsafe { settimeofday(&tv, std::ptr::null()) } != 0 {
+        anyhow::bail!("settimeofday failed");
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "espidf"))]
+fn set_system_time(_time: DateTime<Utc>) -> anyhow::Result<()> {
+    anyhow::bail!("Setting the system time is only supported on the device")
+}
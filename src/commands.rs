@@ -1,6 +1,8 @@
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+use crate::ocpp::{self, Action, Request, Response};
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum MessageType {
     Call = 2,
@@ -8,62 +10,286 @@ pub enum MessageType {
     CallError = 4,
 }
 
-impl From<i8> for MessageType {
-    fn from(s: i8) -> Self {
+impl TryFrom<i8> for MessageType {
+    type Error = anyhow::Error;
+
+    fn try_from(s: i8) -> anyhow::Result<Self> {
         match s {
-            2 => MessageType::Call,
-            3 => MessageType::CallResult,
-            4 => MessageType::CallError,
-            _ => panic!("Invalid message type"),
+            2 => Ok(MessageType::Call),
+            3 => Ok(MessageType::CallResult),
+            4 => Ok(MessageType::CallError),
+            _ => Err(anyhow::anyhow!("Invalid message type {}", s)),
+        }
+    }
+}
+
+/// ErrorCode
+/// The OCPP-J error codes a CallError can carry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    NotImplemented,
+    NotSupported,
+    InternalError,
+    ProtocolError,
+    SecurityError,
+    FormationViolation,
+    PropertyConstraintViolation,
+    OccurenceConstraintViolation,
+    TypeConstraintViolation,
+    GenericError,
+}
+
+impl ErrorCode {
+    /// The OCPP 1.6 name of the error code, `ocpp::error_code` gives the one of the protocol in use
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotImplemented => "NotImplemented",
+            ErrorCode::NotSupported => "NotSupported",
+            ErrorCode::InternalError => "InternalError",
+            ErrorCode::ProtocolError => "ProtocolError",
+            ErrorCode::SecurityError => "SecurityError",
+            ErrorCode::FormationViolation => "FormationViolation",
+            ErrorCode::PropertyConstraintViolation => "PropertyConstraintViolation",
+            ErrorCode::OccurenceConstraintViolation => "OccurenceConstraintViolation",
+            ErrorCode::TypeConstraintViolation => "TypeConstraintViolation",
+            ErrorCode::GenericError => "GenericError",
+        }
+    }
+    /// Reads the error code of a received CallError, the spellings of OCPP 2.0.1 (which many 1.6
+    /// central systems use as well) are accepted and anything unknown becomes a GenericError
+    pub fn parse(code: &str) -> Self {
+        match code {
+            "NotImplemented" => ErrorCode::NotImplemented,
+            "NotSupported" => ErrorCode::NotSupported,
+            "InternalError" => ErrorCode::InternalError,
+            "ProtocolError" => ErrorCode::ProtocolError,
+            "SecurityError" => ErrorCode::SecurityError,
+            "FormationViolation" | "FormatViolation" => ErrorCode::FormationViolation,
+            "PropertyConstraintViolation" => ErrorCode::PropertyConstraintViolation,
+            "OccurenceConstraintViolation" | "OccurrenceConstraintViolation" => {
+                ErrorCode::OccurenceConstraintViolation
+            }
+            "TypeConstraintViolation" => ErrorCode::TypeConstraintViolation,
+            _ => ErrorCode::GenericError,
         }
     }
 }
 
+/// CallError
+/// A CallError frame `[4, uniqueId, errorCode, errorDescription, errorDetails]`
+#[derive(Debug, Clone)]
+pub struct CallError {
+    pub unique_id: String,
+    pub error_code: ErrorCode,
+    pub error_description: String,
+    pub error_details: serde_json::Value,
+}
+
+impl CallError {
+    pub fn new(unique_id: &str, error_code: ErrorCode, error_description: &str) -> Self {
+        Self {
+            unique_id: unique_id.to_string(),
+            error_code,
+            error_description: error_description.to_string(),
+            error_details: serde_json::json!({}),
+        }
+    }
+    /// CallError for `error` raised while handling the Call with `unique_id`
+    pub fn from_error(unique_id: &str, error: anyhow::Error) -> Self {
+        if let Some(call_error) = error.downcast_ref::<CallError>() {
+            return Self::new(
+                unique_id,
+                call_error.error_code,
+                &call_error.error_description,
+            );
+        }
+        match error.downcast_ref::<serde_json::Error>() {
+            Some(e) => Self::from_payload_error(unique_id, e),
+            None => Self::new(unique_id, ErrorCode::InternalError, &error.to_string()),
+        }
+    }
+    /// CallError for a payload that could not be (de)serialized
+    pub fn from_payload_error(unique_id: &str, error: &serde_json::Error) -> Self {
+        let error_code = if error.is_data() {
+            ErrorCode::TypeConstraintViolation
+        } else {
+            ErrorCode::FormationViolation
+        };
+        Self::new(unique_id, error_code, &error.to_string())
+    }
+    pub fn to_ocpp_json_message(&self) -> anyhow::Result<String, serde_json::Error> {
+        serde_json::to_string(&(
+            MessageType::CallError as i8,
+            self.unique_id.clone(),
+            ocpp::error_code(self.error_code),
+            self.error_description.clone(),
+            self.error_details.clone(),
+        ))
+    }
+    pub fn from_ocpp_json_message(json_message: &[u8]) -> anyhow::Result<Self> {
+        let (_, unique_id, error_code, error_description, error_details) = serde_json::from_slice::<
+            (i8, String, String, String, serde_json::Value),
+        >(json_message)?;
+        Ok(CallError {
+            unique_id,
+            error_code: ErrorCode::parse(&error_code),
+            error_description,
+            error_details,
+        })
+    }
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.error_code, self.error_description)
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// OCPPRequest
+/// A Call frame `[2, uniqueId, action, payload]`
 #[derive(Debug, Clone)]
 pub struct OCPPRequest {
     pub message_type_id: MessageType,
     pub unique_id: String,
-    pub action: String,
-    pub payload: serde_json::Value,
+    pub payload: Request,
 }
 
 impl OCPPRequest {
-    pub fn to_ocpp_json_message(&self) -> anyhow::Result<String, serde_json::Error> {
-        serde_json::to_string(&(
+    pub fn new(unique_id: String, payload: impl Into<Request>) -> Self {
+        Self {
+            message_type_id: MessageType::Call,
+            unique_id,
+            payload: payload.into(),
+        }
+    }
+    pub fn action(&self) -> Action {
+        self.payload.action()
+    }
+    /// Parses a Call frame, an unknown action or invalid payload fails with a `CallError`
+    pub fn from_ocpp_json_message(json_message: &[u8]) -> anyhow::Result<Self> {
+        let (message_type_id, unique_id, action, payload) =
+            serde_json::from_slice::<(i8, String, String, serde_json::Value)>(json_message)?;
+        let payload = ocpp::decode_request(&action, payload)
+            .map_err(|e| CallError::from_error(&unique_id, e))?;
+        Ok(OCPPRequest {
+            message_type_id: message_type_id.try_into()?,
+            unique_id,
+            payload,
+        })
+    }
+    pub fn to_ocpp_json_message(&self) -> anyhow::Result<String> {
+        let (action, payload) = ocpp::encode_request(&self.payload)?;
+        Ok(serde_json::to_string(&(
             self.message_type_id.clone() as i8,
             self.unique_id.clone(),
-            self.action.clone(),
-            self.payload.clone(),
-        ))
+            action,
+            payload,
+        ))?)
     }
 }
 
+/// OCPPResponse
+/// A CallResult frame `[3, uniqueId, payload]`, the action is resolved from the pending Call with the same unique id
 #[derive(Debug, Clone)]
 pub struct OCPPResponse {
     pub message_type_id: MessageType,
-    pub action: String,
+    pub unique_id: String,
     pub payload: serde_json::Value,
 }
 
 impl OCPPResponse {
+    pub fn new(unique_id: String, payload: &Response) -> anyhow::Result<Self> {
+        Ok(Self {
+            message_type_id: MessageType::CallResult,
+            unique_id,
+            payload: ocpp::encode_response(payload)?,
+        })
+    }
     pub fn to_ocpp_json_message(&self) -> anyhow::Result<String, serde_json::Error> {
         serde_json::to_string(&(
-            self.message_type_id.clone(),
-            self.action.clone(),
+            self.message_type_id.clone() as i8,
+            self.unique_id.clone(),
             self.payload.clone(),
         ))
     }
     pub fn from_ocpp_json_message(json_message: &[u8]) -> anyhow::Result<Self> {
-        let (message_type_id, action, payload) =
+        let (message_type_id, unique_id, payload) =
             serde_json::from_slice::<(i8, String, serde_json::Value)>(json_message)?;
         Ok(OCPPResponse {
-            message_type_id: message_type_id.into(),
-            action,
+            message_type_id: message_type_id.try_into()?,
+            unique_id,
             payload,
         })
     }
 }
 
+/// OCPPMessage
+/// Any OCPP-J frame, either direction
+#[derive(Debug, Clone)]
+pub enum OCPPMessage {
+    Call(OCPPRequest),
+    CallResult(OCPPResponse),
+    CallError(CallError),
+}
+
+impl OCPPMessage {
+    pub fn to_ocpp_json_message(&self) -> anyhow::Result<String> {
+        match self {
+            OCPPMessage::Call(request) => request.to_ocpp_json_message(),
+            OCPPMessage::CallResult(response) => Ok(response.to_ocpp_json_message()?),
+            OCPPMessage::CallError(error) => Ok(error.to_ocpp_json_message()?),
+        }
+    }
+    /// Parses an OCPP-J frame
+    ///
+    /// # Arguments
+    ///
+    /// * `json_message` - the raw frame as received
+    ///
+    /// # Returns
+    ///
+    /// Result<OCPPMessage> - the parsed frame, a malformed Call with a readable unique id fails with a `CallError` to reply with
+    ///
+    pub fn from_ocpp_json_message(json_message: &[u8]) -> anyhow::Result<Self> {
+        let frame = serde_json::from_slice::<Vec<serde_json::Value>>(json_message)?;
+        let message_type_id = frame
+            .first()
+            .and_then(|value| value.as_i64())
+            .ok_or_else(|| anyhow::anyhow!("Frame has no message type id"))?;
+        match MessageType::try_from(i8::try_from(message_type_id)?)? {
+            MessageType::Call => match OCPPRequest::from_ocpp_json_message(json_message) {
+                Ok(request) => Ok(OCPPMessage::Call(request)),
+                Err(e) if e.is::<CallError>() => Err(e),
+                Err(e) => match frame.get(1).and_then(|value| value.as_str()) {
+                    Some(unique_id) => Err(CallError::new(
+                        unique_id,
+                        ErrorCode::FormationViolation,
+                        &e.to_string(),
+                    )
+                    .into()),
+                    None => Err(e),
+                },
+            },
+            MessageType::CallResult => Ok(OCPPMessage::CallResult(
+                OCPPResponse::from_ocpp_json_message(json_message)?,
+            )),
+            MessageType::CallError => Ok(OCPPMessage::CallError(
+                CallError::from_ocpp_json_message(json_message)?,
+            )),
+        }
+    }
+    /// Short description used for logging and the display
+    pub fn label(&self) -> String {
+        match self {
+            OCPPMessage::Call(request) => request.action().as_str().to_string(),
+            OCPPMessage::CallResult(response) => format!("Result {}", response.unique_id),
+            OCPPMessage::CallError(error) => format!("Error {}", error.unique_id),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UniqueId {
     pub value: i32,
@@ -87,3 +313,110 @@ impl Default for UniqueId {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Call from the central system this build understands
+    #[cfg(not(feature = "v2_0_1"))]
+    const CALL: (&str, Action) = (r#"[2,"1","ClearCache",{}]"#, Action::ClearCache);
+    #[cfg(feature = "v2_0_1")]
+    const CALL: (&str, Action) = (
+        r#"[2,"1","RequestStopTransaction",{"transactionId":"42"}]"#,
+        Action::RemoteStopTransaction,
+    );
+
+    fn call_error(frame: &str) -> CallError {
+        match OCPPMessage::from_ocpp_json_message(frame.as_bytes()).unwrap() {
+            OCPPMessage::CallError(call_error) => call_error,
+            message => panic!("{:?} is not a CallError", message),
+        }
+    }
+
+    fn parse_error(frame: &str) -> CallError {
+        OCPPMessage::from_ocpp_json_message(frame.as_bytes())
+            .unwrap_err()
+            .downcast::<CallError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_the_three_frame_types() {
+        let message = OCPPMessage::from_ocpp_json_message(CALL.0.as_bytes()).unwrap();
+        assert!(
+            matches!(message, OCPPMessage::Call(request) if request.unique_id == "1" && request.action() == CALL.1)
+        );
+
+        let message =
+            OCPPMessage::from_ocpp_json_message(br#"[3,"2",{"status":"Accepted"}]"#).unwrap();
+        assert!(
+            matches!(message, OCPPMessage::CallResult(response) if response.unique_id == "2" && response.payload["status"] == "Accepted")
+        );
+
+        let call_error = call_error(r#"[4,"3","NotSupported","No",{"a":1}]"#);
+        assert_eq!(call_error.unique_id, "3");
+        assert_eq!(call_error.error_code, ErrorCode::NotSupported);
+        assert_eq!(call_error.error_description, "No");
+        assert_eq!(call_error.error_details, serde_json::json!({"a": 1}));
+    }
+
+    #[test]
+    fn rejects_unknown_message_types() {
+        let overflowing = CALL.0.replacen('2', "258", 1);
+        let quoted = CALL.0.replacen('2', "\"2\"", 1);
+        for frame in [r#"[5,"1",{}]"#, &overflowing, &quoted, "[]", "{}"] {
+            let error = OCPPMessage::from_ocpp_json_message(frame.as_bytes()).unwrap_err();
+            // not even a Call, so no CallError is sent back
+            assert!(!error.is::<CallError>(), "{}", frame);
+        }
+    }
+
+    #[test]
+    fn accepts_every_spelling_of_an_error_code() {
+        assert_eq!(
+            call_error(r#"[4,"1","FormationViolation","",{}]"#).error_code,
+            ErrorCode::FormationViolation
+        );
+        assert_eq!(
+            call_error(r#"[4,"1","FormatViolation","",{}]"#).error_code,
+            ErrorCode::FormationViolation
+        );
+        assert_eq!(
+            call_error(r#"[4,"1","OccurrenceConstraintViolation","",{}]"#).error_code,
+            ErrorCode::OccurenceConstraintViolation
+        );
+        // vendor codes still resolve the pending Call by its unique id
+        let call_error = call_error(r#"[4,"7","VendorSpecificCode","",{}]"#);
+        assert_eq!(call_error.unique_id, "7");
+        assert_eq!(call_error.error_code, ErrorCode::GenericError);
+    }
+
+    #[test]
+    fn writes_a_call_error_frame() {
+        let call_error = CallError::new("9", ErrorCode::NotImplemented, "Unknown");
+        let frame: serde_json::Value =
+            serde_json::from_str(&call_error.to_ocpp_json_message().unwrap()).unwrap();
+        assert_eq!(
+            frame,
+            serde_json::json!([4, "9", "NotImplemented", "Unknown", {}])
+        );
+    }
+
+    #[test]
+    fn replies_to_a_malformed_call_with_its_unique_id() {
+        let call_error = parse_error(r#"[2,"5","Unknown",{}]"#);
+        assert_eq!(call_error.unique_id, "5");
+        assert_eq!(call_error.error_code, ErrorCode::NotImplemented);
+
+        let call_error = parse_error(r#"[2,"6",{}]"#);
+        assert_eq!(call_error.unique_id, "6");
+        assert_eq!(call_error.error_code, ErrorCode::FormationViolation);
+
+        // without a unique id there is nothing to reply to
+        let error =
+            OCPPMessage::from_ocpp_json_message(CALL.0.replacen("\"1\"", "6", 1).as_bytes())
+                .unwrap_err();
+        assert!(!error.is::<CallError>());
+    }
+}
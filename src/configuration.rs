@@ -0,0 +1,471 @@
+use std::collections::BTreeMap;
+
+use rust_ocpp::v1_6::messages::change_configuration::*;
+use rust_ocpp::v1_6::messages::get_configuration::{
+    GetConfigurationRequest, GetConfigurationResponse,
+};
+use rust_ocpp::v1_6::types::{ConfigurationStatus, KeyValue};
+
+use crate::config::Config;
+use crate::local_list;
+use crate::smart_charging;
+use crate::storage::Storage;
+
+/// Maximum number of identifications in the Local Authorization List
+/// (the constant of rust_ocpp holds the wrong key)
+pub const LOCAL_AUTH_LIST_MAX_LENGTH: &str = "LocalAuthListMaxLength";
+/// Vendor key, milliseconds the cable switch has to settle after a change
+pub const CABLE_DEBOUNCE_TIME: &str = "CableDebounceTime";
+/// Vendor key, seconds to wait for a CallResult/CallError before a Call is considered lost
+pub const RESPONSE_TIMEOUT: &str = "ResponseTimeout";
+
+const KEY: &str = "configuration";
+
+/// Maximum number of keys in a GetConfiguration
+const MAX_KEYS: usize = 50;
+
+/// Access
+/// Whether and when a change of a configuration key takes effect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+    /// the new value is used after the next reboot
+    RebootRequired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Boolean,
+    Integer { min: u64 },
+    Text,
+}
+
+struct Entry {
+    key: &'static str,
+    kind: Kind,
+    access: Access,
+    value: String,
+    /// value of a RebootRequired key to use after the next reboot
+    next_value: Option<String>,
+}
+
+/// Configuration
+/// The OCPP configuration keys, changes by the central system are persisted
+pub struct Configuration {
+    entries: Vec<Entry>,
+    storage: Box<dyn Storage>,
+}
+
+impl Configuration {
+    /// Loads the values persisted in `storage` over the defaults of `config`
+    pub fn load(config: &Config, storage: Box<dyn Storage>) -> Self {
+        let entry = |key, kind, access, value: String| Entry {
+            key,
+            kind,
+            access,
+            value,
+            next_value: None,
+        };
+        let mut entries = vec![
+            entry(
+                AUTHORIZATION_CACHE_ENABLED,
+                Kind::Boolean,
+                Access::ReadWrite,
+                config.ocpp.authorization_cache_enabled.to_string(),
+            ),
+            entry(
+                AUTHORIZE_REMOTE_TX_REQUESTS,
+                Kind::Boolean,
+                Access::ReadOnly,
+                "false".into(),
+            ),
+            entry(
+                CLOCK_ALIGNED_DATA_INTERVAL,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                "0".into(),
+            ),
+            entry(
+                GET_CONFIGURATION_MAX_KEYS,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                MAX_KEYS.to_string(),
+            ),
+            entry(
+                HEARTBEAT_INTERVAL,
+                Kind::Integer { min: 1 },
+                Access::ReadWrite,
+                config.ocpp.heartbeat_interval.to_string(),
+            ),
+            entry(
+                LOCAL_AUTHORIZE_OFFLINE,
+                Kind::Boolean,
+                Access::ReadWrite,
+                config.ocpp.local_authorize_offline.to_string(),
+            ),
+            entry(
+                LOCAL_PRE_AUTHORIZE,
+                Kind::Boolean,
+                Access::ReadWrite,
+                config.ocpp.local_pre_authorize.to_string(),
+            ),
+            entry(
+                METER_VALUES_SAMPLED_DATA,
+                Kind::Text,
+                Access::ReadOnly,
+                "Energy.Active.Import.Register,Power.Active.Import,Current.Import".into(),
+            ),
+            entry(
+                METER_VALUE_SAMPLE_INTERVAL,
+                Kind::Integer { min: 0 },
+                Access::ReadWrite,
+                config.ocpp.meter_value_sample_interval.to_string(),
+            ),
+            entry(
+                NUMBER_OF_CONNECTORS,
+                Kind::Integer { min: 1 },
+                Access::ReadOnly,
+                "1".into(),
+            ),
+            entry(
+                STOP_TRANSACTION_ON_INVALID_ID,
+                Kind::Boolean,
+                Access::ReadOnly,
+                "true".into(),
+            ),
+            entry(
+                SUPPORTED_FEATURE_PROFILES,
+                Kind::Text,
+                Access::ReadOnly,
+                "Core,FirmwareManagement,LocalAuthListManagement,Reservation,SmartCharging,RemoteTrigger"
+                    .into(),
+            ),
+            entry(
+                LOCAL_AUTH_LIST_ENABLED,
+                Kind::Boolean,
+                Access::ReadWrite,
+                "true".into(),
+            ),
+            entry(
+                LOCAL_AUTH_LIST_MAX_LENGTH,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                local_list::MAX_LENGTH.to_string(),
+            ),
+            entry(
+                SEND_LOCAL_LIST_MAX_LENGTH,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                local_list::MAX_LENGTH.to_string(),
+            ),
+            entry(
+                CHARGE_PROFILE_MAX_STACK_LEVEL,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                smart_charging::MAX_STACK_LEVEL.to_string(),
+            ),
+            entry(
+                CHARGING_SCHEDULE_ALLOWED_CHARGING_RATE_UNIT,
+                Kind::Text,
+                Access::ReadOnly,
+                "Current,Power".into(),
+            ),
+            entry(
+                CHARGING_SCHEDULE_MAX_PERIODS,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                smart_charging::MAX_PERIODS.to_string(),
+            ),
+            entry(
+                MAX_CHARGING_PROFILES_INSTALLED,
+                Kind::Integer { min: 0 },
+                Access::ReadOnly,
+                smart_charging::MAX_PROFILES.to_string(),
+            ),
+            entry(
+                RESERVE_CONNECTOR_ZERO_SUPPORTED,
+                Kind::Boolean,
+                Access::ReadOnly,
+                "false".into(),
+            ),
+            entry(
+                CABLE_DEBOUNCE_TIME,
+                Kind::Integer { min: 0 },
+                Access::ReadWrite,
+                "400".into(),
+            ),
+            entry(
+                RESPONSE_TIMEOUT,
+                Kind::Integer { min: 1 },
+                Access::RebootRequired,
+                config.ocpp.response_timeout.to_string(),
+            ),
+        ];
+
+        let stored: BTreeMap<String, String> = match storage.load(KEY) {
+            Ok(Some(data)) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::error!("Discarding unreadable configuration: {}", e);
+                BTreeMap::new()
+            }),
+            Ok(None) => BTreeMap::new(),
+            Err(e) => {
+                log::error!("Failed to load the configuration: {}", e);
+                BTreeMap::new()
+            }
+        };
+        for entry in entries.iter_mut() {
+            if let Some(value) = stored.get(entry.key) {
+                if entry.access != Access::ReadOnly && entry.kind.is_valid(value) {
+                    entry.value = value.clone();
+                }
+            }
+        }
+
+        Self { entries, storage }
+    }
+
+    /// The value of `key`, None for an unknown key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entry(key).map(|entry| entry.value.as_str())
+    }
+
+    /// The value of boolean `key`, false for an unknown key
+    pub fn boolean(&self, key: &str) -> bool {
+        self.get(key)
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    }
+
+    /// The value of integer `key`, 0 for an unknown key
+    pub fn integer(&self, key: &str) -> u64 {
+        self.get(key)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Answers a GetConfiguration, all keys when none are requested
+    pub fn get_configuration(&self, request: &GetConfigurationRequest) -> GetConfigurationResponse {
+        let key_value = |entry: &Entry| KeyValue {
+            key: entry.key.to_string(),
+            readonly: entry.access == Access::ReadOnly,
+            value: Some(entry.value.clone()),
+        };
+        let Some(keys) = request.key.as_ref().filter(|keys| !keys.is_empty()) else {
+            return GetConfigurationResponse {
+                configuration_key: Some(self.entries.iter().map(key_value).collect()),
+                unknown_key: None,
+            };
+        };
+        let mut configuration_key = Vec::new();
+        let mut unknown_key = Vec::new();
+        for key in keys.iter().take(MAX_KEYS) {
+            match self.entry(key) {
+                Some(entry) => configuration_key.push(key_value(entry)),
+                None => unknown_key.push(key.clone()),
+            }
+        }
+        GetConfigurationResponse {
+            configuration_key: Some(configuration_key).filter(|keys| !keys.is_empty()),
+            unknown_key: Some(unknown_key).filter(|keys| !keys.is_empty()),
+        }
+    }
+
+    /// Changes the value of a key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the configuration key
+    /// * `value` - the new value
+    ///
+    /// # Returns
+    ///
+    /// ConfigurationStatus - NotSupported for an unknown key, Rejected for a read-only key, an invalid value
+    /// or when the value can't be persisted, RebootRequired when the value is used after a reboot
+    ///
+    pub fn change(&mut self, key: &str, value: &str) -> ConfigurationStatus {
+        let Some(index) = self.entries.iter().position(|entry| entry.key == key) else {
+            return ConfigurationStatus::NotSupported;
+        };
+        let entry = &self.entries[index];
+        if entry.access == Access::ReadOnly || !entry.kind.is_valid(value) {
+            return ConfigurationStatus::Rejected;
+        }
+        let access = entry.access;
+        let value = match entry.kind {
+            Kind::Boolean => value.to_ascii_lowercase(),
+            _ => value.to_string(),
+        };
+        let entry = &mut self.entries[index];
+        let previous = (entry.value.clone(), entry.next_value.clone());
+        match access {
+            // keep running with the current value
+            Access::RebootRequired => entry.next_value = Some(value),
+            _ => entry.value = value,
+        }
+        if let Err(e) = self.persist() {
+            log::error!("Failed to store the configuration: {}", e);
+            let entry = &mut self.entries[index];
+            (entry.value, entry.next_value) = previous;
+            return ConfigurationStatus::Rejected;
+        }
+        match access {
+            Access::RebootRequired => ConfigurationStatus::RebootRequired,
+            _ => ConfigurationStatus::Accepted,
+        }
+    }
+
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+
+    fn persist(&mut self) -> anyhow::Result<()> {
+        let stored: BTreeMap<&str, &str> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.access != Access::ReadOnly)
+            .map(|entry| {
+                (
+                    entry.key,
+                    entry.next_value.as_ref().unwrap_or(&entry.value).as_str(),
+                )
+            })
+            .collect();
+        let data = serde_json::to_vec(&stored)?;
+        self.storage.store(KEY, &data)
+    }
+}
+
+impl Kind {
+    fn is_valid(&self, value: &str) -> bool {
+        match self {
+            Kind::Boolean => {
+                value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+            }
+            Kind::Integer { min } => value.parse::<u64>().is_ok_and(|value| value >= *min),
+            Kind::Text => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    fn configuration(storage: &MemoryStorage) -> Configuration {
+        Configuration::load(&Config::default(), Box::new(storage.clone()))
+    }
+
+    #[test]
+    fn a_change_depends_on_the_access_of_the_key() {
+        let mut configuration = configuration(&MemoryStorage::new());
+        assert_eq!(
+            configuration.change(NUMBER_OF_CONNECTORS, "2"),
+            ConfigurationStatus::Rejected
+        );
+        assert_eq!(configuration.get(NUMBER_OF_CONNECTORS), Some("1"));
+
+        assert_eq!(
+            configuration.change(HEARTBEAT_INTERVAL, "120"),
+            ConfigurationStatus::Accepted
+        );
+        assert_eq!(configuration.integer(HEARTBEAT_INTERVAL), 120);
+
+        // the current value is kept until the reboot
+        let timeout = configuration.integer(RESPONSE_TIMEOUT);
+        assert_eq!(
+            configuration.change(RESPONSE_TIMEOUT, "5"),
+            ConfigurationStatus::RebootRequired
+        );
+        assert_eq!(configuration.integer(RESPONSE_TIMEOUT), timeout);
+
+        assert_eq!(
+            configuration.change("Unknown", "1"),
+            ConfigurationStatus::NotSupported
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let mut configuration = configuration(&MemoryStorage::new());
+        for (key, value) in [
+            (LOCAL_AUTH_LIST_ENABLED, "yes"),
+            (HEARTBEAT_INTERVAL, "abc"),
+            (HEARTBEAT_INTERVAL, "-1"),
+            // below the minimum of 1
+            (HEARTBEAT_INTERVAL, "0"),
+        ] {
+            assert_eq!(
+                configuration.change(key, value),
+                ConfigurationStatus::Rejected,
+                "{} = {}",
+                key,
+                value
+            );
+        }
+        assert!(configuration.boolean(LOCAL_AUTH_LIST_ENABLED));
+
+        assert_eq!(
+            configuration.change(LOCAL_AUTH_LIST_ENABLED, "FALSE"),
+            ConfigurationStatus::Accepted
+        );
+        assert_eq!(configuration.get(LOCAL_AUTH_LIST_ENABLED), Some("false"));
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let configuration = configuration(&MemoryStorage::new());
+        let response = configuration.get_configuration(&GetConfigurationRequest {
+            key: Some(vec![NUMBER_OF_CONNECTORS.into(), "Unknown".into()]),
+        });
+        let keys = response.configuration_key.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].key, NUMBER_OF_CONNECTORS);
+        assert!(keys[0].readonly);
+        assert_eq!(keys[0].value.as_deref(), Some("1"));
+        assert_eq!(response.unknown_key, Some(vec!["Unknown".to_string()]));
+
+        let response = configuration.get_configuration(&GetConfigurationRequest {
+            key: Some(vec!["Unknown".into()]),
+        });
+        assert_eq!(response.configuration_key, None);
+
+        // all keys without a key
+        let response = configuration.get_configuration(&GetConfigurationRequest { key: None });
+        assert_eq!(
+            response.configuration_key.unwrap().len(),
+            configuration.entries.len()
+        );
+        assert_eq!(response.unknown_key, None);
+    }
+
+    #[test]
+    fn changes_are_persisted() {
+        let storage = MemoryStorage::new();
+        let mut configuration = configuration(&storage);
+        configuration.change(HEARTBEAT_INTERVAL, "120");
+        configuration.change(RESPONSE_TIMEOUT, "5");
+
+        let reloaded = self::configuration(&storage);
+        assert_eq!(reloaded.integer(HEARTBEAT_INTERVAL), 120);
+        assert_eq!(reloaded.integer(RESPONSE_TIMEOUT), 5);
+    }
+
+    #[test]
+    fn stored_read_only_and_invalid_values_are_ignored() {
+        let mut storage = MemoryStorage::new();
+        storage
+            .store(
+                KEY,
+                br#"{"NumberOfConnectors":"2","HeartbeatInterval":"0","LocalPreAuthorize":"true"}"#,
+            )
+            .unwrap();
+        let configuration = configuration(&storage);
+        assert_eq!(configuration.get(NUMBER_OF_CONNECTORS), Some("1"));
+        assert_eq!(
+            configuration.integer(HEARTBEAT_INTERVAL),
+            Config::default().ocpp.heartbeat_interval
+        );
+        assert!(configuration.boolean(LOCAL_PRE_AUTHORIZE));
+    }
+}
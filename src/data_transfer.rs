@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rust_ocpp::v1_6::messages::data_transfer::{DataTransferRequest, DataTransferResponse};
+use rust_ocpp::v1_6::types::DataTransferStatus;
+
+use crate::commands::{OCPPMessage, OCPPRequest, UniqueId};
+use crate::queue::{FifoQueue, Queue};
+
+/// DataTransferHandler
+/// Handles the data of a DataTransfer from the central system and returns the response
+pub type DataTransferHandler =
+    Box<dyn Fn(Option<String>) -> anyhow::Result<DataTransferResponse> + Send>;
+
+/// DataTransferDispatcher
+/// Routes DataTransfers from the central system to the handler registered for their vendorId
+/// and messageId
+#[derive(Default)]
+pub struct DataTransferDispatcher {
+    handlers: HashMap<String, HashMap<Option<String>, DataTransferHandler>>,
+}
+
+impl DataTransferDispatcher {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers the handler for `message_id` of `vendor_id`, the handler without a messageId
+    /// gets the messages of the vendor that have no handler of their own
+    pub fn register<F>(&mut self, vendor_id: &str, message_id: Option<&str>, handler: F)
+    where
+        F: Fn(Option<String>) -> anyhow::Result<DataTransferResponse> + Send + 'static,
+    {
+        self.handlers
+            .entry(vendor_id.into())
+            .or_default()
+            .insert(message_id.map(Into::into), Box::new(handler));
+    }
+
+    /// Runs the handler for a DataTransfer
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - the DataTransfer received from the central system
+    ///
+    /// # Returns
+    ///
+    /// anyhow::Result<DataTransferResponse> - the response of the handler, UnknownVendorId or UnknownMessageId when there is no handler
+    ///
+    pub fn dispatch(&self, request: DataTransferRequest) -> anyhow::Result<DataTransferResponse> {
+        let Some(handlers) = self.handlers.get(&request.vendor_string) else {
+            return Ok(DataTransferResponse {
+                status: DataTransferStatus::UnknownVendorId,
+                data: None,
+            });
+        };
+        let Some(handler) = handlers
+            .get(&request.message_id)
+            .or_else(|| handlers.get(&None))
+        else {
+            return Ok(DataTransferResponse {
+                status: DataTransferStatus::UnknownMessageId,
+                data: None,
+            });
+        };
+        handler(request.data)
+    }
+}
+
+/// DataTransferClient
+/// Sends DataTransfers to the central system and hands their responses to the threads waiting
+/// for them
+#[derive(Clone)]
+pub struct DataTransferClient {
+    send_queue: Arc<FifoQueue<OCPPMessage>>,
+    unique_id: Arc<Mutex<UniqueId>>,
+    waiting: Arc<Mutex<HashMap<String, mpsc::Sender<anyhow::Result<DataTransferResponse>>>>>,
+    /// how long `send` waits, including the time the Call waits in the send queue
+    timeout: Duration,
+}
+
+impl DataTransferClient {
+    pub fn new(
+        send_queue: Arc<FifoQueue<OCPPMessage>>,
+        unique_id: Arc<Mutex<UniqueId>>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            send_queue,
+            unique_id,
+            waiting: Arc::new(Mutex::new(HashMap::new())),
+            timeout,
+        }
+    }
+
+    /// Sends a DataTransfer and waits for its response, the receive thread delivers it, so this
+    /// must not be called from that thread or a Call handler
+    ///
+    /// # Arguments
+    ///
+    /// * `vendor_id` - the vendor the message belongs to
+    /// * `message_id` - the message of the vendor
+    /// * `data` - the vendor specific data
+    ///
+    /// # Returns
+    ///
+    /// anyhow::Result<DataTransferResponse> - the response, or an error for a CallError or when no response arrived in time
+    ///
+    pub fn send(
+        &self,
+        vendor_id: &str,
+        message_id: Option<&str>,
+        data: Option<String>,
+    ) -> anyhow::Result<DataTransferResponse> {
+        let (sender, receiver) = mpsc::channel();
+        let unique_id = self.unique_id.lock().unwrap().next_id().to_string();
+        self.waiting
+            .lock()
+            .unwrap()
+            .insert(unique_id.clone(), sender);
+        self.send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+            unique_id.clone(),
+            DataTransferRequest {
+                vendor_string: vendor_id.into(),
+                message_id: message_id.map(Into::into),
+                data,
+            },
+        )));
+        let result = receiver.recv_timeout(self.timeout);
+        self.waiting.lock().unwrap().remove(&unique_id);
+        result.unwrap_or_else(|_| {
+            Err(anyhow::anyhow!(
+                "No response to DataTransfer {} within {:?}",
+                unique_id,
+                self.timeout
+            ))
+        })
+    }
+
+    /// Hands the outcome of the DataTransfer sent with `unique_id` to the thread waiting for it
+    pub fn resolve(&self, unique_id: &str, result: anyhow::Result<DataTransferResponse>) {
+        match self.waiting.lock().unwrap().remove(unique_id) {
+            Some(sender) => {
+                let _ = sender.send(result);
+            }
+            None => log::warn!("Nobody is waiting for DataTransfer {} anymore", unique_id),
+        }
+    }
+}
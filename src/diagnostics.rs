@@ -0,0 +1,388 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::http;
+use crate::storage::Storage;
+
+/// Bytes of log lines kept in RAM
+pub const RAM_CAPACITY: usize = 16 * 1024;
+/// Bytes of log lines in a chunk spilled to flash
+const CHUNK_SIZE: usize = 2 * 1024;
+/// Number of chunks kept in flash, the oldest one is overwritten
+const CHUNKS: usize = 4;
+
+const NEXT_KEY: &str = "next";
+
+struct Line {
+    timestamp: DateTime<Utc>,
+    text: String,
+}
+
+/// LogBuffer
+/// The most recent log lines, the lines it evicts wait to be spilled to flash
+pub struct LogBuffer {
+    lines: VecDeque<Line>,
+    size: usize,
+    capacity: usize,
+    evicted: VecDeque<Line>,
+    evicted_size: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            size: 0,
+            capacity,
+            evicted: VecDeque::new(),
+            evicted_size: 0,
+        }
+    }
+
+    /// Adds a line, the oldest lines are evicted once the buffer is over its capacity
+    pub fn push(&mut self, timestamp: DateTime<Utc>, text: String) {
+        self.size += text.len();
+        self.lines.push_back(Line { timestamp, text });
+        while self.size > self.capacity {
+            let Some(line) = self.lines.pop_front() else {
+                break;
+            };
+            self.size -= line.text.len();
+            self.evicted_size += line.text.len();
+            self.evicted.push_back(line);
+        }
+        // lines that can't be spilled in time are lost
+        while self.evicted_size > 2 * CHUNK_SIZE {
+            let Some(line) = self.evicted.pop_front() else {
+                break;
+            };
+            self.evicted_size -= line.text.len();
+        }
+    }
+
+    /// Takes the evicted lines once there are enough of them to fill a chunk of flash
+    pub fn take_evicted(&mut self) -> Option<String> {
+        if self.evicted_size < CHUNK_SIZE {
+            return None;
+        }
+        let mut chunk = String::with_capacity(self.evicted_size);
+        while let Some(line) = self.evicted.front() {
+            if !chunk.is_empty() && chunk.len() + line.text.len() > CHUNK_SIZE {
+                break;
+            }
+            chunk.push_str(&line.text);
+            self.evicted_size -= line.text.len();
+            self.evicted.pop_front();
+        }
+        Some(chunk)
+    }
+
+    /// The lines logged between `start` and `stop`, oldest first
+    pub fn lines(&self, start: Option<DateTime<Utc>>, stop: Option<DateTime<Utc>>) -> String {
+        self.evicted
+            .iter()
+            .chain(self.lines.iter())
+            .filter(|line| in_range(line.timestamp, start, stop))
+            .map(|line| line.text.as_str())
+            .collect()
+    }
+}
+
+/// FlashLog
+/// The chunks of log lines spilled to flash, they survive a reboot
+pub struct FlashLog {
+    storage: Box<dyn Storage>,
+    /// the chunk that is written next, it holds the oldest lines
+    next: usize,
+}
+
+impl FlashLog {
+    pub fn load(storage: Box<dyn Storage>) -> Self {
+        let next = match storage.load(NEXT_KEY) {
+            Ok(Some(data)) => data.first().map_or(0, |next| *next as usize % CHUNKS),
+            Ok(None) => 0,
+            Err(e) => {
+                log::error!("Failed to load the flash log: {}", e);
+                0
+            }
+        };
+        Self { storage, next }
+    }
+
+    /// Writes a chunk over the oldest one
+    pub fn append(&mut self, chunk: &str) -> anyhow::Result<()> {
+        self.storage
+            .store(&chunk_key(self.next), chunk.as_bytes())?;
+        self.next = (self.next + 1) % CHUNKS;
+        self.storage.store(NEXT_KEY, &[self.next as u8])
+    }
+
+    /// The lines in flash logged between `start` and `stop`, oldest first, lines without a
+    /// timestamp are always included
+    pub fn lines(&self, start: Option<DateTime<Utc>>, stop: Option<DateTime<Utc>>) -> String {
+        let mut lines = String::new();
+        for index in (self.next..self.next + CHUNKS).map(|index| index % CHUNKS) {
+            let chunk = match self.storage.load(&chunk_key(index)) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => continue,
+                Err(e) => {
+                    lines.push_str(&format!("chunk {} is unreadable: {}\n", index, e));
+                    continue;
+                }
+            };
+            for line in String::from_utf8_lossy(&chunk).split_inclusive('\n') {
+                let timestamp = line
+                    .split_once(' ')
+                    .and_then(|(timestamp, _)| DateTime::parse_from_rfc3339(timestamp).ok());
+                if timestamp.map_or(true, |timestamp| {
+                    in_range(timestamp.with_timezone(&Utc), start, stop)
+                }) {
+                    lines.push_str(line);
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// DiagnosticsLogger
+/// Passes records on to another logger and keeps them in a LogBuffer for GetDiagnostics
+pub struct DiagnosticsLogger<L> {
+    inner: L,
+    buffer: Arc<Mutex<LogBuffer>>,
+}
+
+impl<L: log::Log> DiagnosticsLogger<L> {
+    pub fn new(inner: L, buffer: Arc<Mutex<LogBuffer>>) -> Self {
+        Self { inner, buffer }
+    }
+}
+
+impl<L: log::Log> log::Log for DiagnosticsLogger<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.inner.log(record);
+        if !self.inner.enabled(record.metadata()) {
+            return;
+        }
+        // not clock::now(), the clock logs while it is locked, on the device it sets the system time
+        let timestamp = Utc::now();
+        let text = format!(
+            "{} {} {}: {}\n",
+            timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.push(timestamp, text);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Uploads a diagnostics file
+///
+/// # Arguments
+///
+/// * `location` - the http location of the directory the file is uploaded to
+/// * `file_name` - the name of the file in that directory
+/// * `contents` - the log lines
+/// * `retries` - how many times a failed upload is retried
+/// * `retry_interval` - the time to wait before a retry
+///
+/// # Returns
+///
+/// anyhow::Result<()> - the error of the last attempt when all of them failed
+///
+pub fn upload(
+    location: &str,
+    file_name: &str,
+    contents: &str,
+    retries: u32,
+    retry_interval: Duration,
+) -> anyhow::Result<()> {
+    let location = format!("{}/{}", location.trim_end_matches('/'), file_name);
+    let mut attempt = 0;
+    loop {
+        match http::put(&location, contents.as_bytes()) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                log::warn!(
+                    "Upload to {} failed, retry {} of {}: {}",
+                    location,
+                    attempt,
+                    retries,
+                    e
+                );
+                thread::sleep(retry_interval);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn chunk_key(index: usize) -> String {
+    format!("chunk{}", index)
+}
+
+fn in_range(
+    timestamp: DateTime<Utc>,
+    start: Option<DateTime<Utc>>,
+    stop: Option<DateTime<Utc>>,
+) -> bool {
+    start.map_or(true, |start| timestamp >= start) && stop.map_or(true, |stop| timestamp <= stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::http::test_server::{response, serve};
+    use crate::storage::memory::MemoryStorage;
+
+    fn at(second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap()
+    }
+
+    /// A log line of `length` bytes as the DiagnosticsLogger writes them
+    fn line(second: u32, length: usize) -> String {
+        let prefix = format!(
+            "{} INFO test: ",
+            at(second).to_rfc3339_opts(SecondsFormat::Millis, true)
+        );
+        format!("{}{}\n", prefix, "x".repeat(length - prefix.len() - 1))
+    }
+
+    #[test]
+    fn evicts_the_oldest_lines_over_capacity() {
+        let mut buffer = LogBuffer::new(RAM_CAPACITY);
+        for second in 0..16 {
+            buffer.push(at(second), line(second, 1024));
+        }
+        assert_eq!(buffer.lines(None, None).len(), RAM_CAPACITY);
+        assert_eq!(buffer.take_evicted(), None);
+
+        buffer.push(at(16), line(16, 1024));
+        // the evicted line still counts for an upload until it is spilled
+        assert!(buffer.lines(None, None).starts_with(&line(0, 1024)));
+        // but one line doesn't fill a chunk of flash yet
+        assert_eq!(buffer.take_evicted(), None);
+
+        buffer.push(at(17), line(17, 1024));
+        assert_eq!(buffer.take_evicted(), Some(line(0, 1024) + &line(1, 1024)));
+        assert_eq!(buffer.take_evicted(), None);
+        assert!(buffer.lines(None, None).starts_with(&line(2, 1024)));
+    }
+
+    #[test]
+    fn drops_evicted_lines_that_are_not_spilled_in_time() {
+        let mut buffer = LogBuffer::new(RAM_CAPACITY);
+        for second in 0..16 + 6 {
+            buffer.push(at(second), line(second, 1024));
+        }
+        // 2 chunks of evicted lines are kept, the first 2 lines are gone
+        assert!(buffer.lines(None, None).starts_with(&line(2, 1024)));
+        assert_eq!(buffer.take_evicted(), Some(line(2, 1024) + &line(3, 1024)));
+        assert_eq!(buffer.take_evicted(), Some(line(4, 1024) + &line(5, 1024)));
+        assert_eq!(buffer.take_evicted(), None);
+    }
+
+    #[test]
+    fn filters_lines_on_start_and_stop_time() {
+        let mut buffer = LogBuffer::new(RAM_CAPACITY);
+        for second in 0..5 {
+            buffer.push(at(second), line(second, 100));
+        }
+        assert_eq!(
+            buffer.lines(Some(at(1)), Some(at(3))),
+            line(1, 100) + &line(2, 100) + &line(3, 100)
+        );
+        assert_eq!(buffer.lines(Some(at(4)), None), line(4, 100));
+        assert_eq!(buffer.lines(None, Some(at(0))), line(0, 100));
+        assert_eq!(buffer.lines(Some(at(3)), Some(at(1))), "");
+    }
+
+    #[test]
+    fn keeps_the_last_chunks_in_flash_oldest_first() {
+        let storage = MemoryStorage::new();
+        let mut flash_log = FlashLog::load(Box::new(storage.clone()));
+        assert_eq!(flash_log.lines(None, None), "");
+        for second in 0..CHUNKS as u32 {
+            flash_log.append(&line(second, 100)).unwrap();
+        }
+        assert_eq!(
+            flash_log.lines(None, None),
+            (0..CHUNKS as u32)
+                .map(|second| line(second, 100))
+                .collect::<String>()
+        );
+
+        // the oldest chunk is overwritten
+        flash_log.append(&line(4, 100)).unwrap();
+        flash_log.append(&line(5, 100)).unwrap();
+        let expected: String = (2..6).map(|second| line(second, 100)).collect();
+        assert_eq!(flash_log.lines(None, None), expected);
+
+        // and the order survives a reboot
+        let mut flash_log = FlashLog::load(Box::new(storage));
+        assert_eq!(flash_log.lines(None, None), expected);
+        flash_log.append(&line(6, 100)).unwrap();
+        assert_eq!(
+            flash_log.lines(None, None),
+            (3..7).map(|second| line(second, 100)).collect::<String>()
+        );
+    }
+
+    #[test]
+    fn filters_flash_lines_on_start_and_stop_time() {
+        let mut flash_log = FlashLog::load(Box::new(MemoryStorage::new()));
+        let chunk = line(0, 100) + &line(1, 100) + "no timestamp\n" + &line(2, 100);
+        flash_log.append(&chunk).unwrap();
+        // lines without a timestamp are kept
+        assert_eq!(
+            flash_log.lines(Some(at(1)), Some(at(1))),
+            line(1, 100) + "no timestamp\n"
+        );
+    }
+
+    #[test]
+    fn uploads_the_lines() {
+        let (location, server) = serve(vec![
+            response("500 Internal Server Error", Some(0), b""),
+            response("200 OK", Some(0), b""),
+        ]);
+        upload(
+            &format!("{}/logs/", location),
+            "diagnostics.log",
+            "log lines\n",
+            1,
+            Duration::ZERO,
+        )
+        .unwrap();
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        let request = String::from_utf8(requests[1].clone()).unwrap();
+        assert!(request.starts_with("PUT /logs/diagnostics.log HTTP/1.0\r\n"));
+        assert!(request.ends_with("\r\n\r\nlog lines\n"));
+    }
+
+    #[test]
+    fn gives_up_an_upload_after_the_retries() {
+        let (location, _server) = serve(vec![response("403 Forbidden", Some(0), b"")]);
+        let error = upload(&location, "diagnostics.log", "", 0, Duration::ZERO).unwrap_err();
+        assert!(error.to_string().contains("status 403"), "{}", error);
+    }
+}
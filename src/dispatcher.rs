@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use crate::commands::{CallError, ErrorCode, OCPPRequest, OCPPResponse};
+use crate::ocpp::{Action, OCPPCall, Request, Response};
+
+/// CallHandler
+/// Handles the payload of a Call from the central system and returns the CallResult payload
+pub type CallHandler = Box<dyn Fn(Request) -> anyhow::Result<Response> + Send>;
+
+/// Dispatcher
+/// Routes Calls initiated by the central system to the handler registered for their action
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: HashMap<Action, CallHandler>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers the handler for the action of request payload `T`
+    pub fn register<T, F>(&mut self, handler: F)
+    where
+        T: OCPPCall + 'static,
+        F: Fn(T) -> anyhow::Result<T::Response> + Send + 'static,
+    {
+        self.handlers.insert(
+            T::ACTION,
+            Box::new(move |request| {
+                let payload = T::from_request(request).ok_or_else(|| {
+                    anyhow::anyhow!("Unexpected payload for {}", T::ACTION.as_str())
+                })?;
+                Ok(handler(payload)?.into())
+            }),
+        );
+    }
+
+    /// Runs the handler for a Call
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - the Call received from the central system
+    ///
+    /// # Returns
+    ///
+    /// Result<OCPPResponse, CallError> - the CallResult to send back, or the CallError when the action is unsupported or the handler failed
+    ///
+    pub fn dispatch(&self, request: OCPPRequest) -> Result<OCPPResponse, CallError> {
+        let action = request.action();
+        let handler = self.handlers.get(&action).ok_or_else(|| {
+            CallError::new(
+                &request.unique_id,
+                ErrorCode::NotSupported,
+                &format!("{} is not supported", action.as_str()),
+            )
+        })?;
+        let response =
+            handler(request.payload).map_err(|e| CallError::from_error(&request.unique_id, e))?;
+        OCPPResponse::new(request.unique_id.clone(), &response)
+            .map_err(|e| CallError::from_error(&request.unique_id, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_ocpp::v1_6::messages::remote_stop_transaction::{
+        RemoteStopTransactionRequest, RemoteStopTransactionResponse,
+    };
+    use rust_ocpp::v1_6::messages::unlock_connector::UnlockConnectorRequest;
+    use rust_ocpp::v1_6::types::RemoteStartStopStatus;
+
+    use super::*;
+
+    // a Call both protocol versions carry
+    const STOP: RemoteStopTransactionRequest = RemoteStopTransactionRequest { transaction_id: 42 };
+
+    fn dispatcher() -> Dispatcher {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(|_: RemoteStopTransactionRequest| {
+            Ok(RemoteStopTransactionResponse {
+                status: RemoteStartStopStatus::Accepted,
+            })
+        });
+        dispatcher
+    }
+
+    #[test]
+    fn a_call_is_answered_by_its_handler() {
+        let response = dispatcher()
+            .dispatch(OCPPRequest::new("1".into(), STOP))
+            .unwrap();
+        assert_eq!(response.unique_id, "1");
+        assert_eq!(response.payload["status"], "Accepted");
+    }
+
+    #[test]
+    fn an_unregistered_action_is_not_supported() {
+        let error = dispatcher()
+            .dispatch(OCPPRequest::new(
+                "2".into(),
+                UnlockConnectorRequest { connector_id: 1 },
+            ))
+            .unwrap_err();
+        assert_eq!(error.unique_id, "2");
+        assert_eq!(error.error_code, ErrorCode::NotSupported);
+    }
+
+    #[test]
+    fn a_failing_handler_is_an_internal_error() {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(
+            |_: RemoteStopTransactionRequest| -> anyhow::Result<RemoteStopTransactionResponse> {
+                anyhow::bail!("storage is gone")
+            },
+        );
+        let error = dispatcher
+            .dispatch(OCPPRequest::new("3".into(), STOP))
+            .unwrap_err();
+        assert_eq!(error.unique_id, "3");
+        assert_eq!(error.error_code, ErrorCode::InternalError);
+        assert_eq!(error.error_description, "storage is gone");
+    }
+
+    #[test]
+    fn a_call_error_of_a_handler_keeps_its_code() {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(
+            |_: RemoteStopTransactionRequest| -> anyhow::Result<RemoteStopTransactionResponse> {
+                Err(CallError::new("", ErrorCode::PropertyConstraintViolation, "no cache").into())
+            },
+        );
+        let error = dispatcher
+            .dispatch(OCPPRequest::new("4".into(), STOP))
+            .unwrap_err();
+        // with the unique id of the Call
+        assert_eq!(error.unique_id, "4");
+        assert_eq!(error.error_code, ErrorCode::PropertyConstraintViolation);
+        assert_eq!(error.error_description, "no cache");
+    }
+}
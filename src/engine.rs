@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::commands::{CallError, OCPPMessage};
+use crate::ocpp::Action;
+use crate::pending::PendingRequests;
+use crate::queue::{FifoQueue, Queue};
+use crate::registration::Registration;
+use crate::transport::{Transport, TransportEvent};
+
+/// EngineEvent
+/// What happened during a poll, for the display and logs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineEvent {
+    Connected,
+    Disconnected,
+    Sent(String),
+}
+
+/// Engine
+/// Moves OCPP-J frames between a transport and the send and receive queues
+///
+/// Has no ESP-IDF dependencies, so it can run against a loopback transport on the host
+pub struct Engine {
+    transport: Box<dyn Transport>,
+    send_queue: Arc<FifoQueue<OCPPMessage>>,
+    receive_queue: Arc<FifoQueue<OCPPMessage>>,
+    pending: Arc<PendingRequests>,
+    registration: Arc<Registration>,
+    /// Calls waiting for the BootNotification to be accepted
+    held: VecDeque<OCPPMessage>,
+}
+
+impl Engine {
+    pub fn new(
+        transport: Box<dyn Transport>,
+        send_queue: Arc<FifoQueue<OCPPMessage>>,
+        receive_queue: Arc<FifoQueue<OCPPMessage>>,
+        pending: Arc<PendingRequests>,
+        registration: Arc<Registration>,
+    ) -> Self {
+        Self {
+            transport,
+            send_queue,
+            receive_queue,
+            pending,
+            registration,
+            held: VecDeque::new(),
+        }
+    }
+
+    /// Queues everything the transport received and sends the next message when connected
+    ///
+    /// Calls other than BootNotification are held back until the registration is accepted
+    ///
+    /// # Returns
+    ///
+    /// Vec<EngineEvent> - connection changes and the message that was sent, if any
+    ///
+    pub fn poll(&mut self) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = self.transport.receive() {
+            match event {
+                TransportEvent::Connected => events.push(EngineEvent::Connected),
+                TransportEvent::Disconnected => events.push(EngineEvent::Disconnected),
+                TransportEvent::Frame(data) => self.receive_frame(&data),
+            }
+        }
+
+        if !self.transport.is_connected() {
+            return events;
+        }
+        let accepted = self.registration.is_accepted();
+        let held = if accepted {
+            self.held.pop_front()
+        } else {
+            None
+        };
+        let message = match held {
+            Some(message) => message,
+            None if !self.send_queue.is_empty() => self.send_queue.pop(),
+            None => return events,
+        };
+        if !accepted {
+            if let OCPPMessage::Call(request) = &message {
+                if request.action() != Action::BootNotification {
+                    self.held.push_back(message);
+                    return events;
+                }
+            }
+        }
+        match message.to_ocpp_json_message() {
+            Ok(json) => {
+                if let OCPPMessage::Call(request) = &message {
+                    self.pending.register(request);
+                }
+                log::info!("Publishing {}", message.label());
+                if let Err(e) = self.transport.send(&json) {
+                    log::error!("Failed to publish message: {}", e);
+                }
+                events.push(EngineEvent::Sent(message.label()));
+            }
+            Err(e) => log::error!("Failed to encode {}: {}", message.label(), e),
+        }
+        events
+    }
+
+    /// Parses a received OCPP-J frame onto the receive queue, malformed Calls are answered with a CallError
+    fn receive_frame(&self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        match OCPPMessage::from_ocpp_json_message(data) {
+            Ok(message) => {
+                self.receive_queue.push(message);
+            }
+            Err(e) => match e.downcast::<CallError>() {
+                Ok(call_error) => {
+                    log::warn!("Rejecting malformed Call: {}", call_error);
+                    self.send_queue.push(OCPPMessage::CallError(call_error));
+                }
+                Err(e) => log::error!("Failed to parse message: {:?}", e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use rust_ocpp::v1_6::types::RegistrationStatus;
+
+    use super::*;
+    use crate::commands::{ErrorCode, OCPPRequest};
+    use crate::messages;
+    use crate::transport::loopback::LoopbackTransport;
+
+    struct Harness {
+        engine: Engine,
+        central_system: LoopbackTransport,
+        send_queue: Arc<FifoQueue<OCPPMessage>>,
+        receive_queue: Arc<FifoQueue<OCPPMessage>>,
+        pending: Arc<PendingRequests>,
+        registration: Arc<Registration>,
+    }
+
+    impl Harness {
+        fn new(timeout: Duration) -> Self {
+            let (charger, central_system) = LoopbackTransport::pair();
+            let send_queue = Arc::new(FifoQueue::new());
+            let receive_queue = Arc::new(FifoQueue::new());
+            let pending = Arc::new(PendingRequests::new(timeout));
+            let registration = Arc::new(Registration::new(Duration::from_secs(60)));
+            let engine = Engine::new(
+                Box::new(charger),
+                send_queue.clone(),
+                receive_queue.clone(),
+                pending.clone(),
+                registration.clone(),
+            );
+            Self {
+                engine,
+                central_system,
+                send_queue,
+                receive_queue,
+                pending,
+                registration,
+            }
+        }
+
+        fn call(&self, unique_id: &str, request: impl Into<crate::ocpp::Request>) {
+            self.send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.into(),
+                request,
+            )));
+        }
+
+        /// The frames the central system received, connection changes are skipped
+        fn received(&mut self) -> Vec<serde_json::Value> {
+            let mut frames = Vec::new();
+            while let Some(event) = self.central_system.receive() {
+                if let TransportEvent::Frame(data) = event {
+                    frames.push(serde_json::from_slice(&data).unwrap());
+                }
+            }
+            frames
+        }
+    }
+
+    #[test]
+    fn holds_calls_until_the_boot_notification_is_accepted() {
+        let mut harness = Harness::new(Duration::from_secs(30));
+        harness.call("1", messages::heartbeat_request());
+        harness.call("2", messages::boot_notification_request());
+
+        assert_eq!(harness.engine.poll(), vec![EngineEvent::Connected]);
+        assert_eq!(
+            harness.engine.poll(),
+            vec![EngineEvent::Sent("BootNotification".into())]
+        );
+        let frames = harness.received();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0][1], "2");
+        assert_eq!(frames[0][2], "BootNotification");
+
+        harness
+            .registration
+            .update(RegistrationStatus::Accepted, 60);
+        assert_eq!(
+            harness.engine.poll(),
+            vec![EngineEvent::Sent("Heartbeat".into())]
+        );
+        let frames = harness.received();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0][1], "1");
+    }
+
+    #[test]
+    fn sends_nothing_while_disconnected() {
+        let mut harness = Harness::new(Duration::from_secs(30));
+        harness
+            .registration
+            .update(RegistrationStatus::Accepted, 60);
+        harness.central_system.set_connected(false);
+        harness.call("1", messages::heartbeat_request());
+
+        assert_eq!(
+            harness.engine.poll(),
+            vec![EngineEvent::Connected, EngineEvent::Disconnected]
+        );
+        assert_eq!(harness.send_queue.len(), 1);
+
+        harness.central_system.set_connected(true);
+        assert_eq!(
+            harness.engine.poll(),
+            vec![
+                EngineEvent::Connected,
+                EngineEvent::Sent("Heartbeat".into())
+            ]
+        );
+        assert_eq!(harness.received().len(), 1);
+    }
+
+    #[test]
+    fn replies_to_a_malformed_call_with_a_call_error() {
+        let mut harness = Harness::new(Duration::from_secs(30));
+        harness.central_system.send(r#"[2,"42",7,{}]"#).unwrap();
+        harness
+            .central_system
+            .send(r#"[2,"43","Unknown",{}]"#)
+            .unwrap();
+
+        // CallErrors are not held back before the registration is accepted
+        harness.engine.poll();
+        harness.engine.poll();
+        assert!(harness.receive_queue.is_empty());
+        let frames = harness.received();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0][0], 4);
+        assert_eq!(frames[0][1], "42");
+        assert_eq!(
+            frames[0][2],
+            crate::ocpp::error_code(ErrorCode::FormationViolation)
+        );
+        assert_eq!(frames[1][1], "43");
+        assert_eq!(
+            frames[1][2],
+            crate::ocpp::error_code(ErrorCode::NotImplemented)
+        );
+    }
+
+    #[test]
+    fn passes_well_formed_frames_to_the_receive_queue() {
+        let mut harness = Harness::new(Duration::from_secs(30));
+        harness
+            .central_system
+            .send(r#"[3,"42",{"currentTime":"2024-01-01T00:00:00Z"}]"#)
+            .unwrap();
+
+        harness.engine.poll();
+        assert_eq!(harness.receive_queue.len(), 1);
+        match harness.receive_queue.pop() {
+            OCPPMessage::CallResult(response) => assert_eq!(response.unique_id, "42"),
+            message => panic!("Unexpected {}", message.label()),
+        }
+        assert!(harness.send_queue.is_empty());
+    }
+
+    #[test]
+    fn expires_a_call_without_a_response() {
+        let mut harness = Harness::new(Duration::from_millis(20));
+        harness.call("1", messages::boot_notification_request());
+        harness.engine.poll();
+        assert!(!harness.pending.is_empty());
+        assert!(harness.pending.expired().is_empty());
+
+        thread::sleep(Duration::from_millis(40));
+        let expired = harness.pending.expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, "1");
+        assert_eq!(expired[0].1.action(), Action::BootNotification);
+        assert!(harness.pending.is_empty());
+        // a response arriving after that is discarded
+        assert!(harness.pending.resolve("1").is_err());
+    }
+}
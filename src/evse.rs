@@ -1,5 +1,11 @@
+use chrono::{DateTime, Utc};
+use rust_ocpp::v1_6::messages::stop_transaction::StopTransactionRequest;
+use rust_ocpp::v1_6::types::{IdTagInfo, Reason};
 use uuid::Uuid;
 
+use crate::clock;
+use crate::messages;
+
 /// ConnectorType
 /// The specific connector type of an EVSE
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
@@ -30,13 +36,70 @@ impl Default for EvseId {
     }
 }
 
+/// Session
+/// The transaction running on an EVSE
+#[derive(PartialEq, Clone, Debug)]
+pub struct Session {
+    pub id_tag: String,
+    /// assigned by the central system in the StartTransactionResponse
+    pub transaction_id: Option<i64>,
+    pub id_tag_info: Option<IdTagInfo>,
+    /// start of the session, Relative charging profiles are scheduled from it
+    pub started_at: DateTime<Utc>,
+    /// StopTransaction of a session that ended before its transaction id arrived
+    stop: Option<StopTransactionRequest>,
+    /// the StartTransaction got a CallError or no response, there will be no transaction id
+    start_failed: bool,
+}
+
+impl Session {
+    pub fn new(id_tag: &str) -> Self {
+        Self {
+            id_tag: id_tag.into(),
+            transaction_id: None,
+            id_tag_info: None,
+            started_at: clock::now(),
+            stop: None,
+            start_failed: false,
+        }
+    }
+}
+
+/// Reservation
+/// A connector held for an idTag with ReserveNow until the expiry date
+#[derive(PartialEq, Clone, Debug)]
+pub struct Reservation {
+    pub reservation_id: i64,
+    pub id_tag: String,
+    /// idTags with this parent idTag may use the reservation as well
+    pub parent_id_tag: Option<String>,
+    pub expiry_date: DateTime<Utc>,
+}
+
+impl Reservation {
+    /// Whether `id_tag`, or an idTag with `parent_id_tag` as its parent, may use the reservation
+    pub fn is_for(&self, id_tag: &str, parent_id_tag: Option<&str>) -> bool {
+        self.id_tag == id_tag
+            || self
+                .parent_id_tag
+                .as_deref()
+                .is_some_and(|parent| parent_id_tag == Some(parent))
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expiry_date
+    }
+}
+
 /// Evse
 /// Electric Vehicle Supply Equipment (The part with the connector and the kWh meter)
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct Evse {
     pub id: EvseId,
     pub connector_type: ConnectorType,
     pub power: u32,
+    pub session: Option<Session>,
+    pub reservation: Option<Reservation>,
 }
 
 impl Evse {
@@ -45,8 +108,115 @@ impl Evse {
             id,
             connector_type,
             power,
+            session: None,
+            reservation: None,
         }
     }
+
+    /// Starts a session, it uses up the reservation of the connector
+    ///
+    /// # Returns
+    ///
+    /// Option<i64> - the id of the reservation, for the StartTransaction
+    ///
+    pub fn start_session(&mut self, id_tag: &str) -> Option<i64> {
+        self.session = Some(Session::new(id_tag));
+        self.reservation
+            .take()
+            .map(|reservation| reservation.reservation_id)
+    }
+
+    pub fn transaction_id(&self) -> Option<i64> {
+        self.session
+            .as_ref()
+            .and_then(|session| session.transaction_id)
+    }
+
+    /// Records the StartTransactionResponse on the session
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - the id assigned by the central system
+    /// * `id_tag_info` - whether the idTag is allowed to charge
+    ///
+    /// # Returns
+    ///
+    /// Option<StopTransactionRequest> - the StopTransaction that was waiting for the transaction id, if the session already ended
+    ///
+    pub fn transaction_started(
+        &mut self,
+        transaction_id: i64,
+        id_tag_info: IdTagInfo,
+    ) -> Option<StopTransactionRequest> {
+        let session = self
+            .session
+            .as_mut()
+            .filter(|session| session.transaction_id.is_none())?;
+        session.transaction_id = Some(transaction_id);
+        session.id_tag_info = Some(id_tag_info);
+        let mut stop = session.stop.take()?;
+        stop.transaction_id = transaction_id;
+        self.session = None;
+        Some(stop)
+    }
+
+    /// Records that the StartTransaction got a CallError or no response, a session that already
+    /// ended is dropped with its StopTransaction, a running one ends without a StopTransaction
+    ///
+    /// # Returns
+    ///
+    /// bool - whether the session was dropped
+    ///
+    pub fn transaction_failed(&mut self) -> bool {
+        let Some(session) = self
+            .session
+            .as_mut()
+            .filter(|session| session.transaction_id.is_none())
+        else {
+            return false;
+        };
+        if session.stop.is_none() {
+            session.start_failed = true;
+            return false;
+        }
+        self.session = None;
+        true
+    }
+
+    /// Ends the session
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - why the transaction stopped
+    /// * `meter_stop` - the energy register in Wh
+    ///
+    /// # Returns
+    ///
+    /// Option<StopTransactionRequest> - the StopTransaction to send, None when there is no session, its transaction id is not known yet or its StartTransaction failed
+    ///
+    pub fn stop_session(
+        &mut self,
+        reason: Reason,
+        meter_stop: i64,
+    ) -> Option<StopTransactionRequest> {
+        let session = self.session.as_mut()?;
+        let request = messages::stop_transaction_request(
+            &session.id_tag,
+            session.transaction_id.unwrap_or_default(),
+            reason,
+            meter_stop,
+        );
+        if session.start_failed {
+            self.session = None;
+            return None;
+        }
+        if session.transaction_id.is_none() {
+            session.stop = Some(request);
+            return None;
+        }
+        self.session = None;
+        Some(request)
+    }
 }
 
 impl Default for Evse {
@@ -55,6 +225,51 @@ impl Default for Evse {
             id: EvseId::new(),
             connector_type: ConnectorType::Type2,
             power: 11,
+            session: None,
+            reservation: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn reservation(parent_id_tag: Option<&str>) -> Reservation {
+        Reservation {
+            reservation_id: 1,
+            id_tag: "tag".into(),
+            parent_id_tag: parent_id_tag.map(String::from),
+            expiry_date: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
         }
     }
+
+    #[test]
+    fn a_reservation_is_for_its_id_tag() {
+        let reservation = reservation(None);
+        assert!(reservation.is_for("tag", None));
+        assert!(reservation.is_for("tag", Some("group")));
+        assert!(!reservation.is_for("other", None));
+        // without a parent idTag of its own the reservation is for nobody else
+        assert!(!reservation.is_for("other", Some("group")));
+    }
+
+    #[test]
+    fn a_reservation_is_for_the_id_tags_of_its_parent() {
+        let reservation = reservation(Some("group"));
+        assert!(reservation.is_for("other", Some("group")));
+        assert!(!reservation.is_for("other", Some("another group")));
+        assert!(!reservation.is_for("other", None));
+        // the parent idTag itself only matches as the parent of the presented idTag
+        assert!(!reservation.is_for("group", None));
+    }
+
+    #[test]
+    fn a_reservation_expires() {
+        let reservation = reservation(None);
+        assert!(!reservation.is_expired(reservation.expiry_date - chrono::Duration::seconds(1)));
+        assert!(reservation.is_expired(reservation.expiry_date));
+    }
 }
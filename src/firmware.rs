@@ -0,0 +1,262 @@
+#[cfg(target_os = "espidf")]
+pub mod ota;
+
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use crate::http;
+
+/// First byte of an ESP application image
+const IMAGE_MAGIC: u8 = 0xE9;
+
+const CHUNK_SIZE: usize = 4096;
+
+/// FirmwareSlot
+/// The flash partition a new firmware image is written to, it is booted after a restart
+pub trait FirmwareSlot: Send {
+    /// Starts writing a new image, an unfinished image is discarded
+    fn begin(&mut self) -> anyhow::Result<()>;
+
+    fn write(&mut self, data: &[u8]) -> anyhow::Result<()>;
+
+    /// Ends the image, fails when it isn't a valid image
+    fn finish(&mut self) -> anyhow::Result<()>;
+
+    /// Boots the finished image on the next restart
+    fn activate(&mut self) -> anyhow::Result<()>;
+
+    /// Confirms the running image works, so the bootloader doesn't roll it back
+    fn mark_valid(&mut self) -> anyhow::Result<()>;
+}
+
+/// MemorySlot
+/// A slot that keeps the image in memory, for running on the host
+#[derive(Default)]
+pub struct MemorySlot {
+    pub image: Vec<u8>,
+    pub finished: bool,
+    pub active: bool,
+}
+
+impl MemorySlot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FirmwareSlot for MemorySlot {
+    fn begin(&mut self) -> anyhow::Result<()> {
+        self.image.clear();
+        self.finished = false;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.image.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.finished = true;
+        Ok(())
+    }
+
+    fn activate(&mut self) -> anyhow::Result<()> {
+        if !self.finished {
+            anyhow::bail!("No finished image to activate");
+        }
+        self.active = true;
+        Ok(())
+    }
+
+    fn mark_valid(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Downloads the firmware image at `location` into `slot`
+///
+/// # Arguments
+///
+/// * `location` - the http location of the image
+/// * `retries` - how many times a failed download is retried
+/// * `retry_interval` - the time to wait before a retry
+/// * `slot` - the partition the image is written to
+///
+/// # Returns
+///
+/// anyhow::Result<()> - the error of the last attempt when all of them failed
+///
+pub fn download(
+    location: &str,
+    retries: u32,
+    retry_interval: Duration,
+    slot: &mut dyn FirmwareSlot,
+) -> anyhow::Result<()> {
+    let mut attempt = 0;
+    loop {
+        let result = http::get(location).and_then(|mut body| {
+            let length = body.length;
+            write_image(&mut body, length, slot)
+        });
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                log::warn!(
+                    "Download of {} failed, retry {} of {}: {}",
+                    location,
+                    attempt,
+                    retries,
+                    e
+                );
+                thread::sleep(retry_interval);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Writes an image to `slot`, fails when it is not an ESP application image or when less
+/// than `length` bytes arrive
+pub fn write_image(
+    image: &mut dyn Read,
+    length: Option<usize>,
+    slot: &mut dyn FirmwareSlot,
+) -> anyhow::Result<()> {
+    slot.begin()?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut written = 0;
+    loop {
+        let read = image.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        if written == 0 && buf[0] != IMAGE_MAGIC {
+            anyhow::bail!("Not an ESP application image");
+        }
+        slot.write(&buf[..read])?;
+        written += read;
+    }
+    if written == 0 {
+        anyhow::bail!("Empty image");
+    }
+    if let Some(length) = length.filter(|length| *length != written) {
+        anyhow::bail!("Image has {} of {} bytes", written, length);
+    }
+    slot.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::test_server::{response, serve};
+
+    fn image(length: usize) -> Vec<u8> {
+        let mut image: Vec<u8> = (0..length).map(|i| i as u8).collect();
+        image[0] = IMAGE_MAGIC;
+        image
+    }
+
+    #[test]
+    fn writes_a_complete_image() {
+        let image = image(2 * CHUNK_SIZE + 10);
+        let mut slot = MemorySlot::new();
+        write_image(&mut image.as_slice(), Some(image.len()), &mut slot).unwrap();
+        assert!(slot.finished);
+        assert_eq!(slot.image, image);
+        slot.activate().unwrap();
+        assert!(slot.active);
+    }
+
+    #[test]
+    fn rejects_an_image_without_the_magic_byte() {
+        let mut image = image(100);
+        image[0] = 0;
+        let mut slot = MemorySlot::new();
+        let error = write_image(&mut image.as_slice(), Some(image.len()), &mut slot).unwrap_err();
+        assert_eq!(error.to_string(), "Not an ESP application image");
+        assert!(!slot.finished);
+        assert!(slot.activate().is_err());
+    }
+
+    #[test]
+    fn rejects_a_truncated_image() {
+        let image = image(100);
+        let mut slot = MemorySlot::new();
+        let error = write_image(&mut image.as_slice(), Some(200), &mut slot).unwrap_err();
+        assert_eq!(error.to_string(), "Image has 100 of 200 bytes");
+        assert!(!slot.finished);
+        assert!(slot.activate().is_err());
+    }
+
+    #[test]
+    fn downloads_an_image() {
+        let image = image(2 * CHUNK_SIZE + 10);
+        let (location, server) = serve(vec![response("200 OK", Some(image.len()), &image)]);
+        let mut slot = MemorySlot::new();
+        download(
+            &format!("{}/app.bin", location),
+            0,
+            Duration::ZERO,
+            &mut slot,
+        )
+        .unwrap();
+        assert!(slot.finished);
+        assert_eq!(slot.image, image);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn downloads_an_image_without_a_length() {
+        let image = image(100);
+        let (location, _server) = serve(vec![response("200 OK", None, &image)]);
+        let mut slot = MemorySlot::new();
+        download(&location, 0, Duration::ZERO, &mut slot).unwrap();
+        assert_eq!(slot.image, image);
+    }
+
+    #[test]
+    fn retries_a_failed_download() {
+        let image = image(100);
+        let (location, server) = serve(vec![
+            response("503 Service Unavailable", Some(0), b""),
+            // cut off halfway
+            response("200 OK", Some(image.len()), &image[..50]),
+            response("200 OK", Some(image.len()), &image),
+        ]);
+        let mut slot = MemorySlot::new();
+        download(&location, 2, Duration::ZERO, &mut slot).unwrap();
+        assert_eq!(slot.image, image);
+        assert_eq!(server.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_the_retries() {
+        let (location, server) = serve(vec![
+            response("404 Not Found", Some(0), b""),
+            response("404 Not Found", Some(0), b""),
+        ]);
+        let mut slot = MemorySlot::new();
+        let error = download(&location, 1, Duration::ZERO, &mut slot).unwrap_err();
+        assert!(error.to_string().contains("status 404"), "{}", error);
+        assert_eq!(server.join().unwrap().len(), 2);
+        assert!(!slot.finished);
+    }
+
+    #[test]
+    fn rejects_a_truncated_or_oversized_body() {
+        let image = image(100);
+        let (location, _server) = serve(vec![
+            response("200 OK", Some(200), &image),
+            response("200 OK", Some(50), &image),
+        ]);
+        let mut slot = MemorySlot::new();
+        let error = download(&location, 0, Duration::ZERO, &mut slot).unwrap_err();
+        assert_eq!(error.to_string(), "Image has 100 of 200 bytes");
+        let error = download(&location, 0, Duration::ZERO, &mut slot).unwrap_err();
+        assert_eq!(error.to_string(), "Image has 100 of 50 bytes");
+        assert!(!slot.finished);
+    }
+}
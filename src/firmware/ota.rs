@@ -0,0 +1,87 @@
+use esp_idf_svc::sys::{
+    esp, esp_ota_abort, esp_ota_begin, esp_ota_end, esp_ota_get_next_update_partition,
+    esp_ota_handle_t, esp_ota_mark_app_valid_cancel_rollback, esp_ota_set_boot_partition,
+    esp_ota_write, esp_partition_t, OTA_SIZE_UNKNOWN,
+};
+
+use super::FirmwareSlot;
+
+/// OtaSlot
+/// The inactive OTA partition of the partition table
+pub struct OtaSlot {
+    partition: *const esp_partition_t,
+    /// handle of the image being written
+    handle: Option<esp_ota_handle_t>,
+    finished: bool,
+}
+
+// the partition points into the partition table in flash, which is never freed
+unsafe impl Send for OtaSlot {}
+
+impl OtaSlot {
+    pub fn new() -> anyhow::Result<Self> {
+        let partition = unsafe { esp_ota_get_next_update_partition(std::ptr::null()) };
+        if partition.is_null() {
+            anyhow::bail!("No OTA partition to update");
+        }
+        Ok(Self {
+            partition,
+            handle: None,
+            finished: false,
+        })
+    }
+
+    fn abort(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            unsafe { esp_ota_abort(handle) };
+        }
+    }
+}
+
+impl FirmwareSlot for OtaSlot {
+    fn begin(&mut self) -> anyhow::Result<()> {
+        self.abort();
+        self.finished = false;
+        let mut handle = 0;
+        esp!(unsafe { esp_ota_begin(self.partition, OTA_SIZE_UNKNOWN as _, &mut handle) })?;
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let Some(handle) = self.handle else {
+            anyhow::bail!("No image started");
+        };
+        esp!(unsafe { esp_ota_write(handle, data.as_ptr() as _, data.len() as _) })?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        // esp_ota_end releases the handle, also when the image is invalid
+        let Some(handle) = self.handle.take() else {
+            anyhow::bail!("No image started");
+        };
+        esp!(unsafe { esp_ota_end(handle) })?;
+        self.finished = true;
+        Ok(())
+    }
+
+    fn activate(&mut self) -> anyhow::Result<()> {
+        if !self.finished {
+            anyhow::bail!("No finished image to activate");
+        }
+        esp!(unsafe { esp_ota_set_boot_partition(self.partition) })?;
+        Ok(())
+    }
+
+    fn mark_valid(&mut self) -> anyhow::Result<()> {
+        esp!(unsafe { esp_ota_mark_app_valid_cancel_rollback() })?;
+        Ok(())
+    }
+}
+
+impl Drop for OtaSlot {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
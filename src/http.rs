@@ -0,0 +1,279 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Url
+/// The parts of an `http://host[:port]/path` location
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    /// Parses an http location, other schemes are not supported
+    pub fn parse(location: &str) -> anyhow::Result<Self> {
+        let Some(rest) = location.strip_prefix("http://") else {
+            anyhow::bail!(
+                "Unsupported location {}, only http:// is supported",
+                location
+            );
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse()?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            anyhow::bail!("No host in {}", location);
+        }
+        Ok(Self {
+            host: host.into(),
+            port,
+            path: path.into(),
+        })
+    }
+}
+
+/// Body
+/// The body of a response, read from the connection as it arrives
+pub struct Body {
+    reader: BufReader<TcpStream>,
+    /// the Content-Length, None when the body ends when the server closes the connection
+    pub length: Option<usize>,
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Fetches `location` with a GET, fails unless the server answers 200 OK
+pub fn get(location: &str) -> anyhow::Result<Body> {
+    let url = Url::parse(location)?;
+    let stream = request(&url, "GET", &[])?;
+    let (status, reader, length) = response(stream)?;
+    if status != 200 {
+        anyhow::bail!("GET {} failed with status {}", location, status);
+    }
+    Ok(Body { reader, length })
+}
+
+/// Sends `body` to `location` with a PUT, fails unless the server answers with a 2xx status
+pub fn put(location: &str, body: &[u8]) -> anyhow::Result<()> {
+    let url = Url::parse(location)?;
+    let stream = request(&url, "PUT", body)?;
+    let (status, _, _) = response(stream)?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("PUT {} failed with status {}", location, status);
+    }
+    Ok(())
+}
+
+/// Sends an HTTP/1.0 request, so the response is never chunked and ends with the connection
+fn request(url: &Url, method: &str, body: &[u8]) -> anyhow::Result<TcpStream> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        url.path,
+        url.host,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(stream)
+}
+
+/// Reads the status line and headers of a response, returns the status, the reader positioned
+/// at the body and the Content-Length
+fn response(stream: TcpStream) -> anyhow::Result<(u16, BufReader<TcpStream>, Option<usize>)> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid status line {:?}", line.trim_end()))?;
+    let mut length = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("Connection closed in the headers");
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    Ok((status, reader, length))
+}
+
+/// A local HTTP server for the tests of the download and upload paths
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// A raw response, `length` is sent as the Content-Length when there is one
+    pub fn response(status: &str, length: Option<usize>, body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.0 {}\r\n", status);
+        if let Some(length) = length {
+            response.push_str(&format!("Content-Length: {}\r\n", length));
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// Answers one connection per response and closes it
+    ///
+    /// # Returns
+    ///
+    /// (String, JoinHandle<Vec<Vec<u8>>>) - the http:// base location of the server, joining it gives the requests it received
+    ///
+    pub fn serve(responses: Vec<Vec<u8>>) -> (String, JoinHandle<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let location = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request = Vec::new();
+                    let mut length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        request.extend_from_slice(line.as_bytes());
+                        if let Some(value) = line.strip_prefix("Content-Length:") {
+                            length = value.trim().parse().unwrap();
+                        }
+                        if line == "\r\n" || line.is_empty() {
+                            break;
+                        }
+                    }
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    request.extend_from_slice(&body);
+                    stream.write_all(&response).unwrap();
+                    request
+                })
+                .collect()
+        });
+        (location, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_server::{response, serve};
+    use super::*;
+
+    #[test]
+    fn parses_http_locations() {
+        assert_eq!(
+            Url::parse("http://example.com:8080/firmware/app.bin").unwrap(),
+            Url {
+                host: "example.com".into(),
+                port: 8080,
+                path: "/firmware/app.bin".into(),
+            }
+        );
+        assert_eq!(
+            Url::parse("http://example.com").unwrap(),
+            Url {
+                host: "example.com".into(),
+                port: 80,
+                path: "/".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_other_locations() {
+        for location in [
+            "https://example.com/app.bin",
+            "ftp://example.com/app.bin",
+            "example.com/app.bin",
+            "http://example.com:/app.bin",
+            "http://example.com:http/app.bin",
+            "http://:8080/app.bin",
+        ] {
+            assert!(Url::parse(location).is_err(), "{}", location);
+        }
+    }
+
+    #[test]
+    fn gets_a_body() {
+        let (location, server) = serve(vec![response("200 OK", Some(5), b"hello")]);
+        let mut body = get(&format!("{}/app.bin", location)).unwrap();
+        assert_eq!(body.length, Some(5));
+        let mut contents = String::new();
+        body.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"GET /app.bin HTTP/1.0\r\n"));
+    }
+
+    #[test]
+    fn gets_a_body_without_a_length() {
+        let (location, _server) = serve(vec![response("200 OK", None, b"hello")]);
+        let mut body = get(&location).unwrap();
+        assert_eq!(body.length, None);
+        let mut contents = String::new();
+        body.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+    }
+
+    #[test]
+    fn fails_a_get_without_200_ok() {
+        let (location, _server) = serve(vec![response("404 Not Found", Some(0), b"")]);
+        let error = get(&location).err().unwrap();
+        assert!(error.to_string().contains("status 404"), "{}", error);
+    }
+
+    #[test]
+    fn puts_a_body() {
+        let (location, server) = serve(vec![
+            response("201 Created", Some(0), b""),
+            response("403 Forbidden", Some(0), b""),
+        ]);
+        put(&format!("{}/upload/log.txt", location), b"log lines").unwrap();
+        assert!(put(&location, b"log lines").is_err());
+
+        let requests = server.join().unwrap();
+        let request = String::from_utf8(requests[0].clone()).unwrap();
+        assert!(request.starts_with("PUT /upload/log.txt HTTP/1.0\r\n"));
+        assert!(request.contains("Content-Length: 9\r\n"));
+        assert!(request.ends_with("\r\n\r\nlog lines"));
+    }
+
+    #[test]
+    fn fails_on_a_garbled_response() {
+        let (location, _server) = serve(vec![b"garbage\r\n\r\n".to_vec()]);
+        assert!(get(&location).is_err());
+        let (location, _server) = serve(vec![b"HTTP/1.0 200 OK\r\nContent-Len".to_vec()]);
+        assert!(get(&location).is_err());
+    }
+}
@@ -39,18 +39,34 @@ impl Led {
         match state {
             State::Error => self.set_from_action("error"),
             State::Available => self.set_from_action("available"),
+            State::Reserved => self.set_from_action("reserved"),
             State::Occupied => self.set_from_action("occupied"),
+            State::Authorizing => self.set_from_action("authorizing"),
             State::Charging => self.set_from_action("charging"),
+            State::Unavailable => self.set_from_action("unavailable"),
             State::Off => self.set_from_action("off"),
         };
     }
 
+    /// Blinks the color of `action` a number of times, ends with the led off
+    pub fn blink(&mut self, action: &str, times: usize) {
+        for _ in 0..times {
+            self.set_from_action(action);
+            sleep(Duration::from_millis(250));
+            self.set_from_action("off");
+            sleep(Duration::from_millis(250));
+        }
+    }
+
     pub fn get_charging_color(&self, action: &str) -> RGBW8 {
         match action {
             "error" => RGBW8::from((255, 0, 0, White(0))), // red
             "available" => RGBW8::from((0, 255, 0, White(0))), // green
+            "reserved" => RGBW8::from((255, 0, 255, White(0))), // magenta
             "occupied" => RGBW8::from((255, 255, 0, White(0))), // yellow
+            "authorizing" => RGBW8::from((0, 255, 255, White(0))), // cyan
             "charging" => RGBW8::from((0, 0, 255, White(0))), // blue
+            "unavailable" => RGBW8::from((0, 0, 0, White(64))), // dim white
             _ => RGBW8::from((0, 0, 0, White(0))),         // off
         }
     }
@@ -65,7 +81,16 @@ impl Default for Led {
 pub fn test_leds() {
     let mut led = Led::new(2);
 
-    let charging_colors = ["available", "occupied", "charging", "error", "off"];
+    let charging_colors = [
+        "available",
+        "reserved",
+        "occupied",
+        "authorizing",
+        "charging",
+        "unavailable",
+        "error",
+        "off",
+    ];
 
     for action in charging_colors.iter() {
         led.set_from_action(action);
@@ -0,0 +1,30 @@
+//! The OCPP charge point logic, everything but the display and LEDs
+//!
+//! The modules that drive ESP-IDF peripherals are only built for the `espidf` target, so the
+//! library and its tests also build on a Linux host.
+
+pub mod auth_cache;
+pub mod availability;
+pub mod cable_lock;
+pub mod charger;
+pub mod clock;
+pub mod commands;
+pub mod config;
+pub mod configuration;
+pub mod data_transfer;
+pub mod diagnostics;
+pub mod dispatcher;
+pub mod engine;
+pub mod evse;
+pub mod firmware;
+pub mod http;
+pub mod local_list;
+pub mod messages;
+pub mod meter;
+pub mod ocpp;
+pub mod pending;
+pub mod queue;
+pub mod registration;
+pub mod smart_charging;
+pub mod storage;
+pub mod transport;
@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+
+use rust_ocpp::v1_6::messages::send_local_list::SendLocalListRequest;
+use rust_ocpp::v1_6::types::{AuthorizationStatus, IdTagInfo, UpdateStatus, UpdateType};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::storage::Storage;
+
+/// Maximum number of idTags in the list (SendLocalListMaxLength)
+pub const MAX_LENGTH: usize = 100;
+
+const KEY: &str = "local_list";
+
+#[derive(Serialize, Deserialize, Default)]
+struct Stored {
+    version: i64,
+    entries: BTreeMap<String, IdTagInfo>,
+}
+
+/// LocalAuthList
+/// The local authorization list managed by the central system with SendLocalList
+pub struct LocalAuthList {
+    version: i64,
+    entries: BTreeMap<String, IdTagInfo>,
+    storage: Box<dyn Storage>,
+}
+
+impl LocalAuthList {
+    /// Loads the list persisted in `storage`, an empty list when there is none or it can't be read
+    pub fn load(storage: Box<dyn Storage>) -> Self {
+        let stored = match storage.load(KEY) {
+            Ok(Some(data)) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::error!("Discarding unreadable local authorization list: {}", e);
+                Stored::default()
+            }),
+            Ok(None) => Stored::default(),
+            Err(e) => {
+                log::error!("Failed to load the local authorization list: {}", e);
+                Stored::default()
+            }
+        };
+        Self {
+            version: stored.version,
+            entries: stored.entries,
+            storage,
+        }
+    }
+
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// The IdTagInfo of `id_tag`, with status Expired once its expiry date has passed
+    pub fn get(&self, id_tag: &str) -> Option<IdTagInfo> {
+        self.entries.get(id_tag).cloned().map(expire)
+    }
+
+    /// Applies a SendLocalList, a Full update replaces the list and a Differential update adds,
+    /// changes or (without IdTagInfo) removes entries
+    pub fn update(&mut self, request: &SendLocalListRequest) -> UpdateStatus {
+        let data = request.local_authorization_list.clone().unwrap_or_default();
+        let entries = match request.update_type {
+            UpdateType::Full => data
+                .into_iter()
+                .filter_map(|data| data.id_tag_info.map(|info| (data.id_tag, info)))
+                .collect(),
+            UpdateType::Differential => {
+                if request.list_version <= self.version {
+                    return UpdateStatus::VersionMismatch;
+                }
+                let mut entries = self.entries.clone();
+                for data in data {
+                    match data.id_tag_info {
+                        Some(info) => entries.insert(data.id_tag, info),
+                        None => entries.remove(&data.id_tag),
+                    };
+                }
+                entries
+            }
+        };
+        if entries.len() > MAX_LENGTH {
+            return UpdateStatus::Failed;
+        }
+
+        let stored = Stored {
+            version: request.list_version,
+            entries,
+        };
+        let result = serde_json::to_vec(&stored)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| self.storage.store(KEY, &data));
+        if let Err(e) = result {
+            log::error!("Failed to store the local authorization list: {}", e);
+            return UpdateStatus::Failed;
+        }
+        self.version = stored.version;
+        self.entries = stored.entries;
+        UpdateStatus::Accepted
+    }
+}
+
+/// Sets the status of `info` to Expired once its expiry date has passed
+pub fn expire(mut info: IdTagInfo) -> IdTagInfo {
+    if info
+        .expiry_date
+        .is_some_and(|expiry_date| clock::is_trusted() && expiry_date < clock::now())
+    {
+        info.status = AuthorizationStatus::Expired;
+    }
+    info
+}
+
+/// Decides a swipe without the central system
+///
+/// # Arguments
+///
+/// * `info` - the IdTagInfo of the presented idTag in the local list or authorization cache
+/// * `online` - whether the central system can be reached
+/// * `pre_authorize` - LocalPreAuthorize, start right away for Accepted idTags
+/// * `authorize_offline` - LocalAuthorizeOffline, use the local list and cache while offline
+///
+/// # Returns
+///
+/// Option<AuthorizationStatus> - the outcome, or None when the central system has to be asked with an Authorize
+///
+pub fn authorize(
+    info: Option<IdTagInfo>,
+    online: bool,
+    pre_authorize: bool,
+    authorize_offline: bool,
+) -> Option<AuthorizationStatus> {
+    let status = info.map(|info| info.status);
+    if online {
+        return status.filter(|status| pre_authorize && *status == AuthorizationStatus::Accepted);
+    }
+    if !authorize_offline {
+        return Some(AuthorizationStatus::Invalid);
+    }
+    Some(status.unwrap_or(AuthorizationStatus::Invalid))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rust_ocpp::v1_6::types::AuthorizationData;
+
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    fn info(status: AuthorizationStatus) -> IdTagInfo {
+        IdTagInfo {
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn request(
+        update_type: UpdateType,
+        list_version: i64,
+        list: &[(&str, Option<AuthorizationStatus>)],
+    ) -> SendLocalListRequest {
+        SendLocalListRequest {
+            list_version,
+            update_type,
+            local_authorization_list: Some(
+                list.iter()
+                    .map(|(id_tag, status)| AuthorizationData {
+                        id_tag: id_tag.to_string(),
+                        id_tag_info: status.clone().map(info),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn status(list: &LocalAuthList, id_tag: &str) -> Option<AuthorizationStatus> {
+        list.get(id_tag).map(|info| info.status)
+    }
+
+    #[test]
+    fn a_full_update_replaces_the_list() {
+        let mut list = LocalAuthList::load(Box::new(MemoryStorage::new()));
+        assert_eq!(list.version(), 0);
+        let full = request(
+            UpdateType::Full,
+            3,
+            &[("a", Some(AuthorizationStatus::Accepted)), ("b", None)],
+        );
+        assert_eq!(list.update(&full), UpdateStatus::Accepted);
+        assert_eq!(list.version(), 3);
+        assert_eq!(status(&list, "a"), Some(AuthorizationStatus::Accepted));
+        // an entry of a Full update without IdTagInfo is left out
+        assert_eq!(status(&list, "b"), None);
+
+        // also to a lower version
+        let full = request(
+            UpdateType::Full,
+            1,
+            &[("c", Some(AuthorizationStatus::Blocked))],
+        );
+        assert_eq!(list.update(&full), UpdateStatus::Accepted);
+        assert_eq!(list.version(), 1);
+        assert_eq!(status(&list, "a"), None);
+        assert_eq!(status(&list, "c"), Some(AuthorizationStatus::Blocked));
+    }
+
+    #[test]
+    fn a_differential_update_changes_the_list() {
+        let mut list = LocalAuthList::load(Box::new(MemoryStorage::new()));
+        let full = request(
+            UpdateType::Full,
+            1,
+            &[
+                ("a", Some(AuthorizationStatus::Accepted)),
+                ("b", Some(AuthorizationStatus::Accepted)),
+            ],
+        );
+        assert_eq!(list.update(&full), UpdateStatus::Accepted);
+
+        let differential = request(
+            UpdateType::Differential,
+            2,
+            &[
+                ("a", Some(AuthorizationStatus::Blocked)),
+                ("b", None),
+                ("c", Some(AuthorizationStatus::Accepted)),
+            ],
+        );
+        assert_eq!(list.update(&differential), UpdateStatus::Accepted);
+        assert_eq!(list.version(), 2);
+        assert_eq!(status(&list, "a"), Some(AuthorizationStatus::Blocked));
+        assert_eq!(status(&list, "b"), None);
+        assert_eq!(status(&list, "c"), Some(AuthorizationStatus::Accepted));
+    }
+
+    #[test]
+    fn a_differential_update_needs_a_newer_version() {
+        let mut list = LocalAuthList::load(Box::new(MemoryStorage::new()));
+        let full = request(
+            UpdateType::Full,
+            5,
+            &[("a", Some(AuthorizationStatus::Accepted))],
+        );
+        assert_eq!(list.update(&full), UpdateStatus::Accepted);
+        for version in [4, 5] {
+            let differential = request(UpdateType::Differential, version, &[("a", None)]);
+            assert_eq!(list.update(&differential), UpdateStatus::VersionMismatch);
+        }
+        assert_eq!(list.version(), 5);
+        assert_eq!(status(&list, "a"), Some(AuthorizationStatus::Accepted));
+    }
+
+    #[test]
+    fn fails_an_update_over_the_maximum_length() {
+        let mut list = LocalAuthList::load(Box::new(MemoryStorage::new()));
+        let id_tags: Vec<String> = (0..=MAX_LENGTH).map(|i| format!("tag{}", i)).collect();
+        let entries: Vec<(&str, Option<AuthorizationStatus>)> = id_tags
+            .iter()
+            .map(|id_tag| (id_tag.as_str(), Some(AuthorizationStatus::Accepted)))
+            .collect();
+        let full = request(UpdateType::Full, 1, &entries);
+        assert_eq!(list.update(&full), UpdateStatus::Failed);
+        assert_eq!(list.version(), 0);
+        assert_eq!(status(&list, "tag0"), None);
+
+        let full = request(UpdateType::Full, 1, &entries[..MAX_LENGTH]);
+        assert_eq!(list.update(&full), UpdateStatus::Accepted);
+    }
+
+    #[test]
+    fn survives_a_reboot() {
+        let storage = MemoryStorage::new();
+        let mut list = LocalAuthList::load(Box::new(storage.clone()));
+        let full = request(
+            UpdateType::Full,
+            7,
+            &[("a", Some(AuthorizationStatus::Accepted))],
+        );
+        assert_eq!(list.update(&full), UpdateStatus::Accepted);
+
+        let list = LocalAuthList::load(Box::new(storage.clone()));
+        assert_eq!(list.version(), 7);
+        assert_eq!(status(&list, "a"), Some(AuthorizationStatus::Accepted));
+
+        // an unreadable list is discarded
+        let mut storage = storage;
+        storage.store(KEY, b"not json").unwrap();
+        let list = LocalAuthList::load(Box::new(storage));
+        assert_eq!(list.version(), 0);
+        assert_eq!(status(&list, "a"), None);
+    }
+
+    #[test]
+    fn an_expired_id_tag_is_expired() {
+        clock::set_from_sntp();
+        let mut info = info(AuthorizationStatus::Accepted);
+        info.expiry_date = Some(clock::now() - Duration::minutes(1));
+        assert_eq!(expire(info.clone()).status, AuthorizationStatus::Expired);
+        info.expiry_date = Some(clock::now() + Duration::minutes(1));
+        assert_eq!(expire(info).status, AuthorizationStatus::Accepted);
+    }
+
+    #[test]
+    fn decides_a_swipe_without_the_central_system() {
+        let accepted = || Some(info(AuthorizationStatus::Accepted));
+        // online only Accepted idTags are started right away, and only with LocalPreAuthorize
+        assert_eq!(
+            authorize(accepted(), true, true, true),
+            Some(AuthorizationStatus::Accepted)
+        );
+        assert_eq!(authorize(accepted(), true, false, true), None);
+        assert_eq!(
+            authorize(Some(info(AuthorizationStatus::Blocked)), true, true, true),
+            None
+        );
+        // offline the list decides, unknown idTags are Invalid
+        assert_eq!(
+            authorize(accepted(), false, false, true),
+            Some(AuthorizationStatus::Accepted)
+        );
+        assert_eq!(
+            authorize(None, false, false, true),
+            Some(AuthorizationStatus::Invalid)
+        );
+        assert_eq!(
+            authorize(accepted(), false, false, false),
+            Some(AuthorizationStatus::Invalid)
+        );
+    }
+}
@@ -1,59 +1,237 @@
-use embedded_svc::wifi::{AuthMethod, ClientConfiguration, Configuration};
+use embedded_svc::wifi::{AuthMethod, ClientConfiguration, Configuration as WifiConfiguration};
 
 use esp_idf_hal::i2c::*;
 use esp_idf_hal::peripherals::Peripherals;
+use esp_idf_hal::reset;
 
 use esp_idf_svc as _;
 use esp_idf_svc::eventloop::EspSystemEventLoop;
-use esp_idf_svc::hal::gpio::{InterruptType, PinDriver, Pull};
+use esp_idf_svc::hal::gpio::{Gpio8, InputPin, InterruptType, Output, OutputPin, PinDriver, Pull};
 use esp_idf_svc::hal::task::notification::Notification;
-use esp_idf_svc::mqtt::client::*;
+use esp_idf_svc::log::EspLogger;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::sntp::EspSntp;
 use esp_idf_svc::wifi::EspWifi;
 
+use rust_ocpp::v1_6::messages::cancel_reservation::{
+    CancelReservationRequest, CancelReservationResponse,
+};
+use rust_ocpp::v1_6::messages::change_availability::{
+    ChangeAvailabilityRequest, ChangeAvailabilityResponse,
+};
+use rust_ocpp::v1_6::messages::change_configuration::{
+    ChangeConfigurationRequest, ChangeConfigurationResponse, AUTHORIZATION_CACHE_ENABLED,
+    HEARTBEAT_INTERVAL, LOCAL_AUTHORIZE_OFFLINE, LOCAL_AUTH_LIST_ENABLED, LOCAL_PRE_AUTHORIZE,
+    METER_VALUE_SAMPLE_INTERVAL,
+};
+use rust_ocpp::v1_6::messages::clear_cache::{ClearCacheRequest, ClearCacheResponse};
+use rust_ocpp::v1_6::messages::clear_charging_profile::{
+    ClearChargingProfileRequest, ClearChargingProfileResponse,
+};
+use rust_ocpp::v1_6::messages::data_transfer::{DataTransferRequest, DataTransferResponse};
+use rust_ocpp::v1_6::messages::get_composite_schedule::{
+    GetCompositeScheduleRequest, GetCompositeScheduleResponse,
+};
+use rust_ocpp::v1_6::messages::get_configuration::GetConfigurationRequest;
+use rust_ocpp::v1_6::messages::get_diagnostics::{GetDiagnosticsRequest, GetDiagnosticsResponse};
+use rust_ocpp::v1_6::messages::get_local_list_version::{
+    GetLocalListVersionRequest, GetLocalListVersionResponse,
+};
+use rust_ocpp::v1_6::messages::remote_start_transaction::{
+    RemoteStartTransactionRequest, RemoteStartTransactionResponse,
+};
+use rust_ocpp::v1_6::messages::remote_stop_transaction::{
+    RemoteStopTransactionRequest, RemoteStopTransactionResponse,
+};
+use rust_ocpp::v1_6::messages::reserve_now::{ReserveNowRequest, ReserveNowResponse};
+use rust_ocpp::v1_6::messages::reset::{ResetRequest, ResetResponse};
+use rust_ocpp::v1_6::messages::send_local_list::{SendLocalListRequest, SendLocalListResponse};
+use rust_ocpp::v1_6::messages::set_charging_profile::{
+    SetChargingProfileRequest, SetChargingProfileResponse,
+};
+use rust_ocpp::v1_6::messages::trigger_message::{TriggerMessageRequest, TriggerMessageResponse};
+use rust_ocpp::v1_6::messages::unlock_connector::{
+    UnlockConnectorRequest, UnlockConnectorResponse,
+};
+use rust_ocpp::v1_6::messages::update_firmware::{UpdateFirmwareRequest, UpdateFirmwareResponse};
+use rust_ocpp::v1_6::types::{
+    AuthorizationStatus, AvailabilityStatus, CancelReservationStatus, ChargingProfilePurposeType,
+    ChargingProfileStatus, ChargingRateUnitType, ClearCacheStatus, DataTransferStatus,
+    DiagnosticsStatus, FirmwareStatus, GetCompositeScheduleStatus, IdTagInfo, MessageTrigger,
+    ReadingContext, Reason, RegistrationStatus, RemoteStartStopStatus, ReservationStatus,
+    ResetRequestStatus, ResetResponseStatus, TriggerMessageStatus, UnlockStatus, UpdateStatus,
+};
+
 use ssd1306::{prelude::*, I2CDisplayInterface};
 
 use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::commands::{OCPPResponse, UniqueId};
+use crate::auth_cache::AuthorizationCache;
+use crate::availability::Availability;
+use crate::cable_lock::gpio::GpioCableLock;
+use crate::cable_lock::CableLock;
+use crate::commands::{OCPPMessage, OCPPRequest, UniqueId};
+use crate::configuration::{Configuration, CABLE_DEBOUNCE_TIME, RESPONSE_TIMEOUT};
+use crate::data_transfer::{DataTransferClient, DataTransferDispatcher};
+use crate::diagnostics::{DiagnosticsLogger, FlashLog, LogBuffer};
+use crate::dispatcher::Dispatcher;
 use crate::display::{Display, DisplayData};
+use crate::engine::{Engine, EngineEvent};
+use crate::evse::Reservation;
+use crate::firmware::ota::OtaSlot;
+use crate::firmware::FirmwareSlot;
+use crate::local_list::LocalAuthList;
 use crate::messages::heartbeat_request;
+use crate::meter::{MeterSource, SimulatedMeter};
+use crate::ocpp::{Action, Request, Response};
+use crate::pending::PendingRequests;
 use crate::queue::{FifoQueue, Queue};
+use crate::registration::Registration;
+use crate::smart_charging::ChargingProfiles;
+use crate::storage::nvs::NvsStorage;
+use crate::storage::Storage;
+use crate::transport::mqtt::MqttTransport;
+use crate::transport::websocket::WebSocketTransport;
+use crate::transport::Transport;
+
+use rust_esp32c3::{
+    auth_cache, availability, cable_lock, charger, clock, commands, config, configuration,
+    data_transfer, diagnostics, dispatcher, engine, evse, firmware, local_list, messages, meter,
+    ocpp, pending, queue, registration, smart_charging, storage, transport,
+};
 
-pub mod charger;
-pub mod commands;
-pub mod config;
 pub mod display;
-pub mod evse;
 pub mod leds;
-pub mod messages;
-pub mod queue;
+
+type Relay = PinDriver<'static, Gpio8, Output>;
+
+/// idTag presented by a swipe of the onboard button, the board has no card reader
+const SWIPE_ID_TAG: &str = "123456";
+
+/// NVS key marking that the next boot runs the image of an UpdateFirmware
+const FIRMWARE_INSTALLING: &str = "installing";
+/// Retries of a firmware download when the UpdateFirmware doesn't specify them
+const FIRMWARE_RETRIES: i64 = 3;
+/// Seconds between retries of a firmware download when the UpdateFirmware doesn't specify them
+const FIRMWARE_RETRY_INTERVAL: i64 = 60;
+/// Retries of a diagnostics upload when the GetDiagnostics doesn't specify them
+const DIAGNOSTICS_RETRIES: i64 = 3;
+/// Seconds between retries of a diagnostics upload when the GetDiagnostics doesn't specify them
+const DIAGNOSTICS_RETRY_INTERVAL: i64 = 60;
+/// Seconds between the diagnostic counters sent to the central system with a DataTransfer
+const COUNTERS_INTERVAL: u64 = 3600;
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
-    esp_idf_svc::log::EspLogger::initialize_default();
+
+    // keeps the log lines for GetDiagnostics as well
+    let org_log_buffer = Arc::new(Mutex::new(LogBuffer::new(diagnostics::RAM_CAPACITY)));
+    log::set_logger(Box::leak(Box::new(DiagnosticsLogger::new(
+        EspLogger,
+        org_log_buffer.clone(),
+    ))))
+    .unwrap();
+    EspLogger.initialize();
 
     let config = config::Config::default();
 
     let peripherals = Peripherals::take().unwrap();
+    let nvs = EspDefaultNvsPartition::take()?;
+
+    let org_configuration = Arc::new(Mutex::new(Configuration::load(
+        &config,
+        Box::new(NvsStorage::new(nvs.clone(), "configuration")?),
+    )));
+    let org_availability = Arc::new(Mutex::new(Availability::load(Box::new(NvsStorage::new(
+        nvs.clone(),
+        "availability",
+    )?))));
+    let response_timeout =
+        Duration::from_secs(org_configuration.lock().unwrap().integer(RESPONSE_TIMEOUT));
 
     let org_charger = Arc::new(Mutex::new(charger::Charger::default()));
 
-    let org_command_queue_send = Arc::new(FifoQueue::<commands::OCPPRequest>::new());
+    let org_command_queue_send = Arc::new(FifoQueue::<commands::OCPPMessage>::new());
 
-    let org_command_queue_recieve = Arc::new(FifoQueue::<commands::OCPPResponse>::new());
+    let org_command_queue_recieve = Arc::new(FifoQueue::<commands::OCPPMessage>::new());
 
     let org_unique_id = Arc::new(Mutex::new(UniqueId::new()));
 
+    let org_pending = Arc::new(PendingRequests::new(response_timeout));
+
+    let org_data_transfer = DataTransferClient::new(
+        org_command_queue_send.clone(),
+        org_unique_id.clone(),
+        response_timeout,
+    );
+
+    let org_online = Arc::new(AtomicBool::new(false));
+
+    let org_firmware_status = Arc::new(Mutex::new(FirmwareStatus::Idle));
+
+    let org_diagnostics_status = Arc::new(Mutex::new(DiagnosticsStatus::Idle));
+
+    let org_registration = Arc::new(Registration::new(Duration::from_secs(
+        org_configuration
+            .lock()
+            .unwrap()
+            .integer(HEARTBEAT_INTERVAL),
+    )));
+
     let org_relay = Arc::new(Mutex::new(
         PinDriver::output(peripherals.pins.gpio8).unwrap(),
     ));
 
+    let cable_lock: Option<Box<dyn CableLock>> = if config.charger.cable_lock {
+        Some(Box::new(GpioCableLock::new(
+            peripherals.pins.gpio6.downgrade_output(),
+            peripherals.pins.gpio7.downgrade_input(),
+        )?))
+    } else {
+        None
+    };
+    let org_cable_lock = Arc::new(Mutex::new(cable_lock));
+
+    let org_profiles = Arc::new(Mutex::new(ChargingProfiles::load(
+        Box::new(NvsStorage::new(nvs.clone(), "profiles")?),
+        config.charger.max_current,
+        config.charger.voltage,
+        config.charger.phases,
+    )));
+
+    // the meter is read before locking the charger, the simulated meter locks it to see if it is
+    // charging and how much current the charging profiles offer
     let charger = org_charger.clone();
-    charger.lock().unwrap().set_state(charger::State::Available);
+    let profiles = org_profiles.clone();
+    let org_meter: Arc<Mutex<Box<dyn MeterSource>>> =
+        Arc::new(Mutex::new(Box::new(SimulatedMeter::new(
+            config.charger.max_current,
+            config.charger.voltage,
+            config.charger.phases,
+            move || {
+                let c = charger.lock().unwrap();
+                if c.get_state() != charger::State::Charging {
+                    return 0.0;
+                }
+                let session = c.evses.first().and_then(|evse| evse.session.as_ref());
+                profiles.lock().unwrap().limit(1, clock::now(), session)
+            },
+        ))));
+
+    let charger = org_charger.clone();
+    if org_availability.lock().unwrap().is_operative(1) {
+        charger.lock().unwrap().set_state(charger::State::Available);
+    } else {
+        charger
+            .lock()
+            .unwrap()
+            .set_state(charger::State::Unavailable);
+    }
 
     let relay = org_relay.clone();
     relay.lock().unwrap().set_low()?;
@@ -61,11 +239,27 @@ fn main() -> anyhow::Result<()> {
     // Wifi
 
     let sysloop = EspSystemEventLoop::take()?;
-    let nvs = EspDefaultNvsPartition::take()?;
+
+    let org_local_list = Arc::new(Mutex::new(LocalAuthList::load(Box::new(NvsStorage::new(
+        nvs.clone(),
+        "local_list",
+    )?))));
+    let org_auth_cache = Arc::new(Mutex::new(AuthorizationCache::load(Box::new(
+        NvsStorage::new(nvs.clone(), "auth_cache")?,
+    ))));
+    let mut firmware_storage = NvsStorage::new(nvs.clone(), "firmware")?;
+    let mut flash_log = if config.charger.log_to_flash {
+        Some(FlashLog::load(Box::new(NvsStorage::new(
+            nvs.clone(),
+            "diagnostics",
+        )?)))
+    } else {
+        None
+    };
 
     let mut wifi = EspWifi::new(peripherals.modem, sysloop, Some(nvs))?;
 
-    wifi.set_configuration(&Configuration::Client(ClientConfiguration {
+    wifi.set_configuration(&WifiConfiguration::Client(ClientConfiguration {
         ssid: config.ssid.as_str().into(),
         password: config.password.as_str().into(),
         auth_method: AuthMethod::None,
@@ -86,6 +280,9 @@ fn main() -> anyhow::Result<()> {
     let ip = wifi.get_configuration().unwrap();
     log::info!("Connected to wifi {:?}", ip);
 
+    // keeps synchronizing in the background, until then the time comes from the central system
+    let _sntp = EspSntp::new_with_callback(&Default::default(), |_| clock::set_from_sntp())?;
+
     let i2c = peripherals.i2c0;
     let sda = peripherals.pins.gpio21;
     let scl = peripherals.pins.gpio20;
@@ -110,65 +307,65 @@ fn main() -> anyhow::Result<()> {
     let d = display.clone();
     d.lock().unwrap().set_data(display_data);
     d.lock().unwrap().refresh();
-    // MQTT
+    // Connection to the central system
 
-    let conf = MqttClientConfiguration {
-        client_id: Some(&config.mqtt.client_id),
-        ..Default::default()
+    let transport: Box<dyn Transport> = match config.transport {
+        config::TransportKind::Mqtt => Box::new(MqttTransport::new(&config.mqtt, &config.charger)?),
+        config::TransportKind::WebSocket => Box::new(WebSocketTransport::new(
+            &config.websocket,
+            &config.charger.serial,
+        )?),
     };
 
-    let broker = config.mqtt.broker.clone();
-    let command_receive_queue = org_command_queue_recieve.clone();
-    let mut client = EspMqttClient::new(&config.mqtt.broker, &conf, move |message_event| {
-        match message_event.as_ref().unwrap() {
-            Event::Connected(_) => log::info!("Connected to MQTT {}", broker),
-            Event::Subscribed(id) => log::info!("Subscribed to {} id", id),
-            Event::Received(msg) => {
-                log::info!("Received message: {}", String::from_utf8_lossy(msg.data()));
-                if !msg.data().is_empty() {
-                    match OCPPResponse::from_ocpp_json_message(msg.data()) {
-                        Ok(response) => {
-                            command_receive_queue.push(response);
-                        }
-                        Err(e) => {
-                            log::error!("Failed to parse message: {:?}", e);
-                        }
-                    }
-                }
-            }
-            _ => log::info!("Unhandled event: {:?}", message_event),
-        };
-    })?;
-
-    let topic = format!(
-        "/system/{}/{}",
-        &config.charger.model, &config.charger.serial
-    );
-    client.subscribe(&topic, QoS::AtLeastOnce)?;
-
     let d = display.clone();
-    d.lock().unwrap().set_message("MQTT Connected".to_string());
+    d.lock().unwrap().set_message("Connecting..".to_string());
     d.lock().unwrap().refresh();
 
     thread::sleep(Duration::from_millis(500));
 
+    // boot thread, repeats the BootNotification until the central system accepts it
+    let send_queue = org_command_queue_send.clone();
     let unique_id = org_unique_id.clone();
-    let command = commands::OCPPRequest {
-        message_type_id: commands::MessageType::Call,
-        unique_id: unique_id.lock().unwrap().next_id().to_string(),
-        action: "BootNotification".to_string(),
-        payload: messages::boot_notification_request()?,
-    };
-
-    log::info!("sending BootNotification: {:?}", command);
+    let registration = org_registration.clone();
+    let pending = org_pending.clone();
+    let online = org_online.clone();
+    thread::spawn(move || loop {
+        // a BootNotification sent while offline would only pile up in the send queue
+        if !online.load(Ordering::SeqCst) || pending.contains(Action::BootNotification) {
+            thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+        let command = OCPPRequest::new(
+            unique_id.lock().unwrap().next_id().to_string(),
+            messages::boot_notification_request(),
+        );
+        log::info!("sending BootNotification: {:?}", command);
 
-    org_command_queue_send.clone().push(command);
+        let updates = registration.updates();
+        let sent_at = Instant::now();
+        send_queue.push(OCPPMessage::Call(command));
+        while registration.updates() == updates && sent_at.elapsed() < response_timeout {
+            thread::sleep(Duration::from_millis(100));
+        }
+        if registration.is_accepted() {
+            break;
+        }
+        thread::sleep(registration.interval());
+    });
 
     // onboard button thread
     let send_queue = org_command_queue_send.clone();
     let unique_id = org_unique_id.clone();
     let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
     let charger = org_charger.clone();
+    let meter = org_meter.clone();
+    let registration = org_registration.clone();
+    let local_list = org_local_list.clone();
+    let auth_cache = org_auth_cache.clone();
+    let online = org_online.clone();
+    let configuration = org_configuration.clone();
+    let d = display.clone();
     thread::spawn(move || {
         let mut button = PinDriver::input(peripherals.pins.gpio9).unwrap();
         button.set_pull(Pull::Up).unwrap();
@@ -189,44 +386,103 @@ fn main() -> anyhow::Result<()> {
             button.enable_interrupt().unwrap();
             notification.wait(esp_idf_svc::hal::delay::BLOCK);
 
+            let energy = meter_register(&meter);
             let mut c = charger.lock().unwrap();
-            let mut r = relay.lock().unwrap();
+            if matches!(
+                c.get_state(),
+                charger::State::Occupied | charger::State::Reserved
+            ) && !registration.is_accepted()
+            {
+                log::warn!("Not starting a transaction before the BootNotification is accepted");
+                d.lock().unwrap().set_message("! Not accepted".to_string());
+                d.lock().unwrap().refresh();
+                continue;
+            }
+            let mut local_status = None;
+            let mut parent_id_tag = None;
             let res = c.transition(charger::ChargerInput::Swipe);
             match res {
-                Ok((_, charger::ChargerOutput::LockedAndPowerIsOn)) => {
-                    r.set_high().unwrap();
-                    send_queue.push(commands::OCPPRequest {
-                        message_type_id: commands::MessageType::Call,
-                        unique_id: unique_id.lock().unwrap().next_id().to_string(),
-                        action: "StartTransaction".to_string(),
-                        payload: messages::start_transaction_request().unwrap(),
-                    });
+                Ok((_, charger::ChargerOutput::AwaitingAuthorization)) => {
+                    let configuration = configuration.lock().unwrap();
+                    let info = configuration
+                        .boolean(LOCAL_AUTH_LIST_ENABLED)
+                        .then(|| local_list.lock().unwrap().get(SWIPE_ID_TAG))
+                        .flatten()
+                        .or_else(|| {
+                            configuration
+                                .boolean(AUTHORIZATION_CACHE_ENABLED)
+                                .then(|| auth_cache.lock().unwrap().get(SWIPE_ID_TAG))
+                                .flatten()
+                        });
+                    parent_id_tag = info.as_ref().and_then(|info| info.parent_id_tag.clone());
+                    local_status = local_list::authorize(
+                        info,
+                        online.load(Ordering::SeqCst),
+                        configuration.boolean(LOCAL_PRE_AUTHORIZE),
+                        configuration.boolean(LOCAL_AUTHORIZE_OFFLINE),
+                    );
+                    if local_status.is_none() {
+                        send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                            unique_id.lock().unwrap().next_id().to_string(),
+                            messages::authorize_request(SWIPE_ID_TAG),
+                        )));
+                    }
                 }
                 Ok((_, charger::ChargerOutput::Unlocked)) => {
-                    r.set_low().unwrap();
-                    send_queue.push(commands::OCPPRequest {
-                        message_type_id: commands::MessageType::Call,
-                        unique_id: unique_id.lock().unwrap().next_id().to_string(),
-                        action: "StopTransaction".to_string(),
-                        payload: messages::stop_transaction_request().unwrap(),
-                    });
+                    if let Err(e) = power_off(&relay, &cable_lock) {
+                        log::error!("Failed to stop charging: {}", e);
+                    }
+                    if let Some(stop) = c
+                        .evse_mut(1)
+                        .and_then(|evse| evse.stop_session(Reason::Local, energy))
+                    {
+                        send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                            unique_id.lock().unwrap().next_id().to_string(),
+                            stop,
+                        )));
+                    }
                 }
                 Ok((_, charger::ChargerOutput::Errored)) => {
-                    r.set_low().unwrap();
+                    if let Err(e) = power_off(&relay, &cable_lock) {
+                        log::error!("Failed to stop charging: {}", e);
+                    }
                     c.set_state(charger::State::Error);
                     thread::sleep(Duration::from_secs(5));
                     c.set_state(charger::State::Available);
                 }
+                Ok(_) => {}
                 Err(e) => {
                     log::warn!("Charger transition failed: {}", e);
                 }
             }
+            drop(c);
+
+            if let Some(status) = local_status {
+                log::info!("{} authorized locally: {:?}", SWIPE_ID_TAG, status);
+                if status != AuthorizationStatus::Accepted {
+                    d.lock().unwrap().set_message(format!("! {:?}", status));
+                    d.lock().unwrap().refresh();
+                }
+                finish_authorization(
+                    &charger,
+                    &relay,
+                    &cable_lock,
+                    &meter,
+                    &send_queue,
+                    &unique_id,
+                    SWIPE_ID_TAG,
+                    parent_id_tag.as_deref(),
+                    status,
+                );
+            }
         }
     });
 
     // cable switch thread
     let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
     let charger = org_charger.clone();
+    let configuration = org_configuration.clone();
     thread::spawn(move || {
         let mut button = PinDriver::input(peripherals.pins.gpio10).unwrap();
         button.set_pull(Pull::Up).unwrap();
@@ -246,7 +502,9 @@ fn main() -> anyhow::Result<()> {
                 match res {
                     Ok((_, charger::ChargerOutput::Errored)) => {
                         log::info!("Charger errored.");
-                        relay.lock().unwrap().set_low().unwrap();
+                        if let Err(e) = power_off(&relay, &cable_lock) {
+                            log::error!("Failed to stop charging: {}", e);
+                        }
                         c.set_state(charger::State::Error);
                     }
                     Err(e) => {
@@ -255,108 +513,1168 @@ fn main() -> anyhow::Result<()> {
                     _ => {}
                 }
                 //debounce
-                thread::sleep(Duration::from_millis(400));
+                thread::sleep(Duration::from_millis(
+                    configuration.lock().unwrap().integer(CABLE_DEBOUNCE_TIME),
+                ));
             }
             thread::sleep(Duration::from_millis(100));
         }
     });
 
-    // report thread
+    // report thread, shows state changes and reports them to the central system, takes the
+    // connector out of service once the transaction or reservation of a Scheduled
+    // ChangeAvailability has ended, drops the TxProfiles of ended transactions and expires
+    // reservations
     let d = display.clone();
     let charger = org_charger.clone();
+    let availability = org_availability.clone();
+    let profiles = org_profiles.clone();
+    let send_queue = org_command_queue_send.clone();
+    let unique_id = org_unique_id.clone();
     thread::spawn(move || {
         let mut led = leds::Led::new(2);
         let mut old_state = charger::State::Off;
+        let mut old_status = None;
+        #[cfg(not(feature = "v2_0_1"))]
+        let mut old_charger_operative = None;
         loop {
-            let new_state = charger.lock().unwrap().get_state();
-            led.set_from_state(new_state);
-            if old_state != charger.lock().unwrap().get_state().clone() {
-                d.lock()
-                    .unwrap()
-                    .set_state(charger.lock().unwrap().get_state().as_str().to_string());
+            // OCPP 2.0.1 only reports the status of connectors
+            #[cfg(not(feature = "v2_0_1"))]
+            {
+                let charger_operative = availability.lock().unwrap().is_operative(0);
+                if old_charger_operative != Some(charger_operative) {
+                    old_charger_operative = Some(charger_operative);
+                    let state = if charger_operative {
+                        charger::State::Available
+                    } else {
+                        charger::State::Unavailable
+                    };
+                    send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                        unique_id.lock().unwrap().next_id().to_string(),
+                        messages::status_notification_request(0, &state, false),
+                    )));
+                }
+            }
+
+            let operative = availability.lock().unwrap().is_operative(1);
+            let (new_state, finishing, in_session) = {
+                let mut c = charger.lock().unwrap();
+                if c.reservation()
+                    .is_some_and(|reservation| reservation.is_expired(clock::now()))
+                {
+                    if let Some(reservation) = c.cancel_reservation() {
+                        log::info!("Reservation {} expired", reservation.reservation_id);
+                    }
+                }
+                if !operative
+                    && matches!(
+                        c.get_state(),
+                        charger::State::Available | charger::State::Occupied
+                    )
+                {
+                    let _ = c.transition(charger::ChargerInput::Inoperative);
+                }
+                let in_session = c.evses.first().is_some_and(|evse| evse.session.is_some());
+                (c.get_state(), c.finishing, in_session)
+            };
+            if !in_session {
+                profiles.lock().unwrap().transaction_ended(1);
+            }
+            if old_state == charger::State::Authorizing
+                && matches!(
+                    new_state,
+                    charger::State::Occupied | charger::State::Reserved
+                )
+            {
+                // the idTag was not accepted
+                led.blink("error", 3);
+            }
+            led.set_from_state(new_state.clone());
+            if old_state != new_state {
+                d.lock().unwrap().set_state(new_state.as_str().to_string());
                 d.lock().unwrap().refresh();
-                old_state = charger.lock().unwrap().get_state();
+                let notification = messages::status_notification_request(1, &new_state, finishing);
+                if old_status.as_ref() != Some(&notification.status) {
+                    old_status = Some(notification.status.clone());
+                    send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                        unique_id.lock().unwrap().next_id().to_string(),
+                        notification,
+                    )));
+                }
+                old_state = new_state;
             }
             thread::sleep(Duration::from_millis(100));
         }
     });
 
-    // mqtt thread publish when send queue is not empty
+    // engine thread, moves frames between the transport and the queues
     let d = display.clone();
-    let send_queue = org_command_queue_send.clone();
+    let online = org_online.clone();
+    let mut engine = Engine::new(
+        transport,
+        org_command_queue_send.clone(),
+        org_command_queue_recieve.clone(),
+        org_pending.clone(),
+        org_registration.clone(),
+    );
     thread::spawn(move || loop {
-        if !send_queue.is_empty() {
-            let command = send_queue.pop();
-            let topic = format!(
-                "/charger/{}/{}",
-                &config.charger.model, &config.charger.serial
-            );
-            log::info!("Publishing {} to topic: {}", command.action, &topic);
-            let result = client.enqueue(
-                &topic,
-                QoS::AtMostOnce,
-                false,
-                command.to_ocpp_json_message().unwrap().as_bytes(),
-            );
-            d.lock()
+        for event in engine.poll() {
+            let message = match event {
+                EngineEvent::Connected => {
+                    online.store(true, Ordering::SeqCst);
+                    "Connected".to_string()
+                }
+                EngineEvent::Disconnected => {
+                    online.store(false, Ordering::SeqCst);
+                    "Disconnected".to_string()
+                }
+                EngineEvent::Sent(label) => format!("-> {}", label),
+            };
+            d.lock().unwrap().set_message(message);
+            d.lock().unwrap().refresh();
+        }
+        thread::sleep(Duration::from_millis(100));
+    });
+
+    // Calls initiated by the central system
+    let mut dispatcher = Dispatcher::new();
+
+    let charger = org_charger.clone();
+    let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
+    let send_queue = org_command_queue_send.clone();
+    let unique_id = org_unique_id.clone();
+    let meter = org_meter.clone();
+    let registration = org_registration.clone();
+    let local_list = org_local_list.clone();
+    let auth_cache = org_auth_cache.clone();
+    let profiles = org_profiles.clone();
+    dispatcher.register(move |request: RemoteStartTransactionRequest| {
+        if !registration.is_accepted() {
+            return Ok(RemoteStartTransactionResponse {
+                status: RemoteStartStopStatus::Rejected,
+            });
+        }
+        // the profile is for the transaction about to start, so it can't name one
+        if request.charging_profile.as_ref().is_some_and(|profile| {
+            profile.charging_profile_purpose != ChargingProfilePurposeType::TxProfile
+                || profile.transaction_id.is_some()
+        }) {
+            log::warn!("Rejecting RemoteStartTransaction with a profile that is not a TxProfile");
+            return Ok(RemoteStartTransactionResponse {
+                status: RemoteStartStopStatus::Rejected,
+            });
+        }
+        let parent_id_tag = local_list
+            .lock()
+            .unwrap()
+            .get(&request.id_tag)
+            .or_else(|| auth_cache.lock().unwrap().get(&request.id_tag))
+            .and_then(|info| info.parent_id_tag);
+        let energy = meter_register(&meter);
+        let mut c = charger.lock().unwrap();
+        if c.reservation().is_some_and(|reservation| {
+            !reservation.is_for(&request.id_tag, parent_id_tag.as_deref())
+        }) {
+            log::warn!("{} may not use the reservation", request.id_tag);
+            return Ok(RemoteStartTransactionResponse {
+                status: RemoteStartStopStatus::Rejected,
+            });
+        }
+        let status = match c.transition(charger::ChargerInput::RemoteStart) {
+            Ok((_, charger::ChargerOutput::LockedAndPowerIsOn)) => {
+                match power_on(&relay, &cable_lock) {
+                    Ok(()) => {
+                        let reservation_id = c
+                            .evse_mut(1)
+                            .and_then(|evse| evse.start_session(&request.id_tag));
+                        if let Some(profile) = request.charging_profile {
+                            let session = c.evses.first().and_then(|evse| evse.session.as_ref());
+                            // removed again by transaction_ended once the session is over
+                            if profiles.lock().unwrap().set(1, profile, session)
+                                != ChargingProfileStatus::Accepted
+                            {
+                                log::warn!(
+                                    "Charging without the profile of the RemoteStartTransaction"
+                                );
+                            }
+                        }
+                        send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                            unique_id.lock().unwrap().next_id().to_string(),
+                            messages::start_transaction_request(
+                                &request.id_tag,
+                                energy,
+                                reservation_id,
+                            ),
+                        )));
+                        RemoteStartStopStatus::Accepted
+                    }
+                    Err(e) => {
+                        log::error!("Failed to start charging: {}", e);
+                        let _ = c.transition(charger::ChargerInput::RemoteStop);
+                        RemoteStartStopStatus::Rejected
+                    }
+                }
+            }
+            _ => RemoteStartStopStatus::Rejected,
+        };
+        Ok(RemoteStartTransactionResponse { status })
+    });
+
+    let charger = org_charger.clone();
+    let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
+    let send_queue = org_command_queue_send.clone();
+    let unique_id = org_unique_id.clone();
+    let meter = org_meter.clone();
+    dispatcher.register(move |request: RemoteStopTransactionRequest| {
+        let energy = meter_register(&meter);
+        let mut c = charger.lock().unwrap();
+        if c.evse_mut(1).and_then(|evse| evse.transaction_id()) != Some(request.transaction_id) {
+            return Ok(RemoteStopTransactionResponse {
+                status: RemoteStartStopStatus::Rejected,
+            });
+        }
+        let status = match c.transition(charger::ChargerInput::RemoteStop) {
+            Ok((_, charger::ChargerOutput::Unlocked)) => {
+                if let Err(e) = power_off(&relay, &cable_lock) {
+                    log::error!("Failed to stop charging: {}", e);
+                }
+                if let Some(stop) = c
+                    .evse_mut(1)
+                    .and_then(|evse| evse.stop_session(Reason::Remote, energy))
+                {
+                    send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                        unique_id.lock().unwrap().next_id().to_string(),
+                        stop,
+                    )));
+                }
+                RemoteStartStopStatus::Accepted
+            }
+            _ => RemoteStartStopStatus::Rejected,
+        };
+        Ok(RemoteStopTransactionResponse { status })
+    });
+
+    let local_list = org_local_list.clone();
+    let configuration = org_configuration.clone();
+    dispatcher.register(move |request: SendLocalListRequest| {
+        if !configuration
+            .lock()
+            .unwrap()
+            .boolean(LOCAL_AUTH_LIST_ENABLED)
+        {
+            return Ok(SendLocalListResponse {
+                status: UpdateStatus::NotSupported,
+            });
+        }
+        let status = local_list.lock().unwrap().update(&request);
+        Ok(SendLocalListResponse { status })
+    });
+
+    let local_list = org_local_list.clone();
+    dispatcher.register(move |_: GetLocalListVersionRequest| {
+        Ok(GetLocalListVersionResponse {
+            list_version: local_list.lock().unwrap().version(),
+        })
+    });
+
+    let auth_cache = org_auth_cache.clone();
+    dispatcher.register(move |_: ClearCacheRequest| {
+        let status = match auth_cache.lock().unwrap().clear() {
+            Ok(()) => ClearCacheStatus::Accepted,
+            Err(e) => {
+                log::error!("Failed to clear the authorization cache: {}", e);
+                ClearCacheStatus::Rejected
+            }
+        };
+        Ok(ClearCacheResponse { status })
+    });
+
+    let configuration = org_configuration.clone();
+    dispatcher.register(move |request: GetConfigurationRequest| {
+        Ok(configuration.lock().unwrap().get_configuration(&request))
+    });
+
+    let configuration = org_configuration.clone();
+    dispatcher.register(move |request: ChangeConfigurationRequest| {
+        let status = configuration
+            .lock()
+            .unwrap()
+            .change(&request.key, &request.value);
+        log::info!(
+            "ChangeConfiguration {}={}: {:?}",
+            request.key,
+            request.value,
+            status
+        );
+        Ok(ChangeConfigurationResponse { status })
+    });
+
+    let charger = org_charger.clone();
+    let availability = org_availability.clone();
+    dispatcher.register(move |request: ChangeAvailabilityRequest| {
+        if request.connector_id > 1 {
+            return Ok(ChangeAvailabilityResponse {
+                status: AvailabilityStatus::Rejected,
+            });
+        }
+        let operative =
+            availability
+                .lock()
                 .unwrap()
-                .set_message(format!("-> {}", command.action));
+                .is_operative_after(request.connector_id, &request.kind, 1);
+
+        // decide before persisting, the report thread applies whatever is stored
+        let mut c = charger.lock().unwrap();
+        let state = c.get_state();
+        let status = availability::apply(&mut c, operative);
+        if status == AvailabilityStatus::Rejected {
+            return Ok(ChangeAvailabilityResponse { status });
+        }
+        if let Err(e) = availability
+            .lock()
+            .unwrap()
+            .change(request.connector_id, request.kind)
+        {
+            log::error!("Failed to store the availability: {}", e);
+            c.set_state(state);
+            return Ok(ChangeAvailabilityResponse {
+                status: AvailabilityStatus::Rejected,
+            });
+        }
+        Ok(ChangeAvailabilityResponse { status })
+    });
+
+    let charger = org_charger.clone();
+    let profiles = org_profiles.clone();
+    dispatcher.register(move |request: SetChargingProfileRequest| {
+        let c = charger.lock().unwrap();
+        let session = c
+            .evses
+            .first()
+            .and_then(|evse| evse.session.as_ref())
+            .filter(|_| request.connector_id == 1);
+        let status = profiles.lock().unwrap().set(
+            request.connector_id,
+            request.cs_charging_profiles,
+            session,
+        );
+        Ok(SetChargingProfileResponse { status })
+    });
+
+    let profiles = org_profiles.clone();
+    dispatcher.register(move |request: ClearChargingProfileRequest| {
+        Ok(ClearChargingProfileResponse {
+            status: profiles.lock().unwrap().clear(&request),
+        })
+    });
+
+    let charger = org_charger.clone();
+    let profiles = org_profiles.clone();
+    dispatcher.register(move |request: GetCompositeScheduleRequest| {
+        if !(0..=1).contains(&request.connector_id) || request.duration <= 0 {
+            return Ok(GetCompositeScheduleResponse {
+                status: GetCompositeScheduleStatus::Rejected,
+                connector_id: None,
+                schedule_start: None,
+                charging_schedule: None,
+            });
+        }
+        let c = charger.lock().unwrap();
+        let session = c
+            .evses
+            .first()
+            .and_then(|evse| evse.session.as_ref())
+            .filter(|_| request.connector_id == 1);
+        let now = clock::now();
+        let schedule = profiles.lock().unwrap().composite_schedule(
+            request.connector_id,
+            now,
+            request.duration,
+            request
+                .charging_rate_unit
+                .unwrap_or(ChargingRateUnitType::A),
+            session,
+        );
+        Ok(GetCompositeScheduleResponse {
+            status: GetCompositeScheduleStatus::Accepted,
+            connector_id: Some(request.connector_id),
+            schedule_start: Some(now),
+            charging_schedule: Some(schedule),
+        })
+    });
+
+    let charger = org_charger.clone();
+    let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
+    let meter = org_meter.clone();
+    let send_queue = org_command_queue_send.clone();
+    let unique_id = org_unique_id.clone();
+    dispatcher.register(move |request: UnlockConnectorRequest| {
+        if request.connector_id != 1 || cable_lock.lock().unwrap().is_none() {
+            return Ok(UnlockConnectorResponse {
+                status: UnlockStatus::NotSupported,
+            });
+        }
+        let energy = meter_register(&meter);
+        let mut c = charger.lock().unwrap();
+        if c.get_state() == charger::State::Charging {
+            let _ = c.transition(charger::ChargerInput::RemoteStop);
+        }
+        if let Some(stop) = c
+            .evse_mut(1)
+            .and_then(|evse| evse.stop_session(Reason::UnlockCommand, energy))
+        {
+            send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.lock().unwrap().next_id().to_string(),
+                stop,
+            )));
+        }
+        let status = match power_off(&relay, &cable_lock) {
+            Ok(()) => UnlockStatus::Unlocked,
+            Err(e) => {
+                log::error!("Failed to unlock the cable: {}", e);
+                UnlockStatus::UnlockFailed
+            }
+        };
+        Ok(UnlockConnectorResponse { status })
+    });
+
+    let charger = org_charger.clone();
+    dispatcher.register(move |request: ReserveNowRequest| {
+        // connector 0 can't be reserved, see ReserveConnectorZeroSupported
+        if request.connector_id != 1 {
+            return Ok(ReserveNowResponse {
+                status: ReservationStatus::Rejected,
+            });
+        }
+        let reservation = Reservation {
+            reservation_id: request.reservation_id,
+            id_tag: request.id_tag,
+            parent_id_tag: request.parent_id_tag,
+            expiry_date: request.expiry_date,
+        };
+        let mut c = charger.lock().unwrap();
+        let replaces = c
+            .reservation()
+            .is_some_and(|old| old.reservation_id == reservation.reservation_id);
+        let status = match c.get_state() {
+            _ if replaces => {
+                if let Some(evse) = c.evse_mut(1) {
+                    evse.reservation = Some(reservation);
+                }
+                ReservationStatus::Accepted
+            }
+            charger::State::Available => {
+                if let Some(evse) = c.evse_mut(1) {
+                    evse.reservation = Some(reservation);
+                }
+                match c.transition(charger::ChargerInput::Reserve) {
+                    Ok(_) => ReservationStatus::Accepted,
+                    Err(_) => {
+                        c.cancel_reservation();
+                        ReservationStatus::Rejected
+                    }
+                }
+            }
+            charger::State::Reserved
+            | charger::State::Occupied
+            | charger::State::Authorizing
+            | charger::State::Charging => ReservationStatus::Occupied,
+            charger::State::Error => ReservationStatus::Faulted,
+            charger::State::Unavailable | charger::State::Off => ReservationStatus::Unavailable,
+        };
+        Ok(ReserveNowResponse { status })
+    });
+
+    let charger = org_charger.clone();
+    dispatcher.register(move |request: CancelReservationRequest| {
+        let mut c = charger.lock().unwrap();
+        let status = if c
+            .reservation()
+            .is_some_and(|reservation| reservation.reservation_id == request.reservation_id)
+        {
+            c.cancel_reservation();
+            CancelReservationStatus::Accepted
+        } else {
+            CancelReservationStatus::Rejected
+        };
+        Ok(CancelReservationResponse { status })
+    });
+
+    // vendor specific messages of the central system, keyed by vendorId and messageId
+    let mut data_transfers = DataTransferDispatcher::new();
+    let d = display.clone();
+    data_transfers.register(&config.charger.vendor, Some("DisplayText"), move |data| {
+        let Some(text) = data else {
+            return Ok(DataTransferResponse {
+                status: DataTransferStatus::Rejected,
+                data: None,
+            });
+        };
+        d.lock().unwrap().set_message(text);
+        d.lock().unwrap().refresh();
+        Ok(DataTransferResponse {
+            status: DataTransferStatus::Accepted,
+            data: None,
+        })
+    });
+    dispatcher.register(move |request: DataTransferRequest| data_transfers.dispatch(request));
+
+    // the receive thread sends the triggered message after the TriggerMessageResponse
+    let (trigger_sender, trigger_receiver) = mpsc::channel::<(MessageTrigger, Option<u64>)>();
+    dispatcher.register(move |request: TriggerMessageRequest| {
+        if request
+            .connector_id
+            .is_some_and(|connector_id| connector_id > 1)
+        {
+            return Ok(TriggerMessageResponse {
+                status: TriggerMessageStatus::Rejected,
+            });
+        }
+        log::info!("{:?} triggered", request.requested_message);
+        trigger_sender.send((request.requested_message, request.connector_id))?;
+        Ok(TriggerMessageResponse {
+            status: TriggerMessageStatus::Accepted,
+        })
+    });
+
+    // work a handler may only start once its response is queued, the receive thread runs it
+    // after pushing the CallResult
+    let (deferred_sender, deferred_receiver) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+
+    // reset thread, stops the transaction and restarts the firmware after a Reset was answered
+    let (reset_sender, reset_receiver) = mpsc::channel::<ResetRequestStatus>();
+    let deferred = deferred_sender.clone();
+    dispatcher.register(move |request: ResetRequest| {
+        log::info!("{:?} reset requested", request.kind);
+        let reset_sender = reset_sender.clone();
+        deferred
+            .send(Box::new(move || {
+                let _ = reset_sender.send(request.kind);
+            }))
+            .map_err(|_| anyhow::anyhow!("The receive thread has stopped"))?;
+        Ok(ResetResponse {
+            status: ResetResponseStatus::Accepted,
+        })
+    });
+
+    let charger = org_charger.clone();
+    let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
+    let meter = org_meter.clone();
+    let send_queue = org_command_queue_send.clone();
+    let pending = org_pending.clone();
+    let unique_id = org_unique_id.clone();
+    let d = display.clone();
+    thread::spawn(move || {
+        let Ok(kind) = reset_receiver.recv() else {
+            return;
+        };
+        d.lock().unwrap().set_message(format!("! {:?} reset", kind));
+        d.lock().unwrap().refresh();
+
+        let (reason, grace) = match kind {
+            ResetRequestStatus::Soft => (Reason::SoftReset, pending.timeout()),
+            ResetRequestStatus::Hard => (Reason::HardReset, Duration::from_secs(1)),
+        };
+        let energy = meter_register(&meter);
+        let mut c = charger.lock().unwrap();
+        if let Err(e) = power_off(&relay, &cable_lock) {
+            log::error!("Failed to stop charging: {}", e);
+        }
+        if let Some(stop) = c
+            .evse_mut(1)
+            .and_then(|evse| evse.stop_session(reason, energy))
+        {
+            send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.lock().unwrap().next_id().to_string(),
+                stop,
+            )));
+        }
+        c.set_state(charger::State::Off);
+        drop(c);
+
+        // a soft reset waits for the queued messages to be answered, a hard reset only for the ResetResponse to go out
+        let started = Instant::now();
+        while started.elapsed() < grace
+            && !(send_queue.is_empty() && (kind == ResetRequestStatus::Hard || pending.is_empty()))
+        {
+            thread::sleep(Duration::from_millis(100));
+        }
+        log::info!("Restarting");
+        reset::restart();
+    });
+
+    // firmware thread, downloads the image of an UpdateFirmware and restarts into it once no
+    // transaction is running
+    let (firmware_sender, firmware_receiver) = mpsc::channel::<UpdateFirmwareRequest>();
+    let deferred = deferred_sender.clone();
+    dispatcher.register(move |request: UpdateFirmwareRequest| {
+        log::info!(
+            "Firmware update from {} at {} requested",
+            request.location,
+            request.retrieve_date
+        );
+        let firmware_sender = firmware_sender.clone();
+        deferred
+            .send(Box::new(move || {
+                let _ = firmware_sender.send(request);
+            }))
+            .map_err(|_| anyhow::anyhow!("The receive thread has stopped"))?;
+        Ok(UpdateFirmwareResponse {})
+    });
+
+    let mut slot = match OtaSlot::new() {
+        Ok(slot) => Some(slot),
+        Err(e) => {
+            log::error!("Firmware updates are not possible: {}", e);
+            None
+        }
+    };
+    let charger = org_charger.clone();
+    let send_queue = org_command_queue_send.clone();
+    let pending = org_pending.clone();
+    let unique_id = org_unique_id.clone();
+    let registration = org_registration.clone();
+    let firmware_status = org_firmware_status.clone();
+    let d = display.clone();
+    thread::spawn(move || {
+        let report = |status: FirmwareStatus| {
+            log::info!("Firmware {:?}", status);
+            // a TriggerMessage reports Idle once the update has ended
+            *firmware_status.lock().unwrap() = match status {
+                FirmwareStatus::Downloading
+                | FirmwareStatus::Downloaded
+                | FirmwareStatus::Installing => status.clone(),
+                _ => FirmwareStatus::Idle,
+            };
+            send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.lock().unwrap().next_id().to_string(),
+                messages::firmware_status_notification_request(status),
+            )));
+        };
+
+        // the image of the previous update is running
+        if matches!(firmware_storage.load(FIRMWARE_INSTALLING), Ok(Some(_))) {
+            while !registration.is_accepted() {
+                thread::sleep(Duration::from_secs(1));
+            }
+            report(FirmwareStatus::Installed);
+            if let Err(e) = firmware_storage.remove(FIRMWARE_INSTALLING) {
+                log::error!("Failed to clear the firmware update: {}", e);
+            }
+        }
+        if let Some(Err(e)) = slot.as_mut().map(|slot| slot.mark_valid()) {
+            log::error!("Failed to confirm the running firmware: {}", e);
+        }
+
+        for request in firmware_receiver {
+            while clock::now() < request.retrieve_date {
+                thread::sleep(Duration::from_secs(1));
+            }
+            report(FirmwareStatus::Downloading);
+            d.lock().unwrap().set_message("! Downloading".to_string());
+            d.lock().unwrap().refresh();
+            let Some(slot) = slot.as_mut() else {
+                report(FirmwareStatus::DownloadFailed);
+                continue;
+            };
+            let retries = request.retries.unwrap_or(FIRMWARE_RETRIES).max(0) as u32;
+            let retry_interval = Duration::from_secs(
+                request
+                    .retry_interval
+                    .unwrap_or(FIRMWARE_RETRY_INTERVAL)
+                    .max(0) as u64,
+            );
+            if let Err(e) = firmware::download(&request.location, retries, retry_interval, slot) {
+                log::error!("Firmware download failed: {}", e);
+                report(FirmwareStatus::DownloadFailed);
+                continue;
+            }
+            report(FirmwareStatus::Downloaded);
+
+            // never restart into the new image during a transaction, Off keeps new ones from starting
+            let previous = loop {
+                let mut c = charger.lock().unwrap();
+                let state = c.get_state();
+                let in_session = c.evses.first().is_some_and(|evse| evse.session.is_some());
+                if !in_session
+                    && matches!(
+                        state,
+                        charger::State::Available
+                            | charger::State::Occupied
+                            | charger::State::Unavailable
+                            | charger::State::Error
+                    )
+                {
+                    c.set_state(charger::State::Off);
+                    break state;
+                }
+                drop(c);
+                thread::sleep(Duration::from_secs(1));
+            };
+
+            report(FirmwareStatus::Installing);
+            d.lock().unwrap().set_message("! Installing".to_string());
             d.lock().unwrap().refresh();
-            if let Err(e) = result {
-                log::error!("Failed to publish message: {:?}", e);
+            if let Err(e) = slot.activate() {
+                log::error!("Failed to install the firmware: {}", e);
+                report(FirmwareStatus::InstallationFailed);
+                charger.lock().unwrap().set_state(previous);
+                continue;
+            }
+            // Installed is reported by the new image
+            if let Err(e) = firmware_storage.store(FIRMWARE_INSTALLING, &[1]) {
+                log::error!("Failed to store the firmware update: {}", e);
+            }
+
+            let started = Instant::now();
+            while started.elapsed() < pending.timeout()
+                && !(send_queue.is_empty() && pending.is_empty())
+            {
+                thread::sleep(Duration::from_millis(100));
+            }
+            log::info!("Restarting into the new firmware");
+            reset::restart();
+        }
+    });
+
+    // diagnostics thread, uploads the log lines of a GetDiagnostics and meanwhile spills the
+    // log lines that no longer fit in RAM to flash
+    let (diagnostics_sender, diagnostics_receiver) =
+        mpsc::channel::<(GetDiagnosticsRequest, String)>();
+    let serial = config.charger.serial.clone();
+    let deferred = deferred_sender.clone();
+    dispatcher.register(move |request: GetDiagnosticsRequest| {
+        let file_name = format!(
+            "diagnostics-{}-{}.log",
+            serial,
+            clock::now().format("%Y%m%dT%H%M%SZ")
+        );
+        log::info!("Diagnostics upload to {} requested", request.location);
+        let diagnostics_sender = diagnostics_sender.clone();
+        let upload = (request, file_name.clone());
+        deferred
+            .send(Box::new(move || {
+                let _ = diagnostics_sender.send(upload);
+            }))
+            .map_err(|_| anyhow::anyhow!("The receive thread has stopped"))?;
+        Ok(GetDiagnosticsResponse {
+            file_name: Some(file_name),
+        })
+    });
+
+    let log_buffer = org_log_buffer.clone();
+    let send_queue = org_command_queue_send.clone();
+    let unique_id = org_unique_id.clone();
+    let diagnostics_status = org_diagnostics_status.clone();
+    thread::spawn(move || {
+        let report = |status: DiagnosticsStatus| {
+            log::info!("Diagnostics {:?}", status);
+            // a TriggerMessage reports Idle once the upload has ended
+            *diagnostics_status.lock().unwrap() = match status {
+                DiagnosticsStatus::Uploading => DiagnosticsStatus::Uploading,
+                _ => DiagnosticsStatus::Idle,
+            };
+            send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.lock().unwrap().next_id().to_string(),
+                messages::diagnostics_status_notification_request(status),
+            )));
+        };
+
+        loop {
+            let (request, file_name) =
+                match diagnostics_receiver.recv_timeout(Duration::from_secs(10)) {
+                    Ok(request) => request,
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        let chunk = log_buffer.lock().unwrap().take_evicted();
+                        if let (Some(flash_log), Some(chunk)) = (flash_log.as_mut(), chunk) {
+                            if let Err(e) = flash_log.append(&chunk) {
+                                log::error!("Failed to spill the log to flash: {}", e);
+                            }
+                        }
+                        continue;
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                };
+            report(DiagnosticsStatus::Uploading);
+
+            let mut contents = flash_log
+                .as_ref()
+                .map(|flash_log| flash_log.lines(request.start_time, request.stop_time))
+                .unwrap_or_default();
+            contents.push_str(
+                &log_buffer
+                    .lock()
+                    .unwrap()
+                    .lines(request.start_time, request.stop_time),
+            );
+            let retries = request.retries.unwrap_or(DIAGNOSTICS_RETRIES).max(0) as u32;
+            let retry_interval = Duration::from_secs(
+                request
+                    .retry_interval
+                    .unwrap_or(DIAGNOSTICS_RETRY_INTERVAL)
+                    .max(0) as u64,
+            );
+            match diagnostics::upload(
+                &request.location,
+                &file_name,
+                &contents,
+                retries,
+                retry_interval,
+            ) {
+                Ok(()) => report(DiagnosticsStatus::Uploaded),
+                Err(e) => {
+                    log::error!("Diagnostics upload failed: {}", e);
+                    report(DiagnosticsStatus::UploadFailed);
+                }
+            }
+        }
+    });
+
+    // counters thread, sends diagnostic counters to the central system until it turns out not
+    // to know them
+    let data_transfer = org_data_transfer.clone();
+    let registration = org_registration.clone();
+    let vendor = config.charger.vendor.clone();
+    thread::spawn(move || {
+        let started = Instant::now();
+        loop {
+            thread::sleep(Duration::from_secs(COUNTERS_INTERVAL));
+            if !registration.is_accepted() {
+                continue;
+            }
+            let counters = serde_json::json!({
+                "uptime": started.elapsed().as_secs(),
+                "freeHeap": unsafe { esp_idf_svc::sys::esp_get_free_heap_size() },
+                "minimumFreeHeap": unsafe { esp_idf_svc::sys::esp_get_minimum_free_heap_size() },
+            });
+            match data_transfer.send(&vendor, Some("Counters"), Some(counters.to_string())) {
+                Ok(response) if response.status == DataTransferStatus::Accepted => {}
+                Ok(response) => {
+                    log::warn!("Counters are not accepted: {:?}", response.status);
+                    return;
+                }
+                Err(e) => log::warn!("Failed to send the counters: {}", e),
             }
         }
-        thread::sleep(Duration::from_millis(100));
     });
 
     // Handle retrieve queue thread
 
     let d = display.clone();
     let receive_queue = org_command_queue_recieve.clone();
+    let send_queue = org_command_queue_send.clone();
+    let pending = org_pending.clone();
+    let charger = org_charger.clone();
+    let relay = org_relay.clone();
+    let cable_lock = org_cable_lock.clone();
+    let meter = org_meter.clone();
+    let unique_id = org_unique_id.clone();
+    let registration = org_registration.clone();
+    let local_list = org_local_list.clone();
+    let auth_cache = org_auth_cache.clone();
+    let configuration = org_configuration.clone();
+    let availability = org_availability.clone();
+    let firmware_status = org_firmware_status.clone();
+    let diagnostics_status = org_diagnostics_status.clone();
+    let data_transfer = org_data_transfer.clone();
     thread::spawn(move || loop {
         if !receive_queue.is_empty() {
-            let response = receive_queue.pop();
+            let response = match receive_queue.pop() {
+                OCPPMessage::Call(request) => {
+                    log::info!("Processing Call: {:?}", request);
+                    let action = request.action();
+                    match dispatcher.dispatch(request) {
+                        Ok(response) => send_queue.push(OCPPMessage::CallResult(response)),
+                        Err(call_error) => {
+                            log::warn!("Failed to handle {}: {}", action.as_str(), call_error);
+                            send_queue.push(OCPPMessage::CallError(call_error));
+                        }
+                    }
+                    while let Ok((trigger, connector_id)) = trigger_receiver.try_recv() {
+                        for request in triggered_requests(
+                            trigger,
+                            connector_id,
+                            &charger,
+                            &availability,
+                            &meter,
+                            &firmware_status,
+                            &diagnostics_status,
+                        ) {
+                            send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                                unique_id.lock().unwrap().next_id().to_string(),
+                                request,
+                            )));
+                        }
+                    }
+                    while let Ok(deferred) = deferred_receiver.try_recv() {
+                        deferred();
+                    }
+                    d.lock()
+                        .unwrap()
+                        .set_message(format!("<- {}", action.as_str()));
+                    d.lock().unwrap().refresh();
+                    continue;
+                }
+                OCPPMessage::CallResult(response) => response,
+                OCPPMessage::CallError(call_error) => {
+                    match pending.resolve(&call_error.unique_id) {
+                        Ok(request) => {
+                            log::error!("{} failed: {}", request.action().as_str(), call_error);
+                            if request.action() == Action::Authorize {
+                                let _ = charger
+                                    .lock()
+                                    .unwrap()
+                                    .transition(charger::ChargerInput::Unauthorized);
+                            }
+                            if request.action() == Action::DataTransfer {
+                                data_transfer
+                                    .resolve(&call_error.unique_id, Err(call_error.clone().into()));
+                            }
+                            if let Request::StartTransaction(start) = &request.request {
+                                transaction_failed(&charger, start.connector_id);
+                            }
+                            d.lock()
+                                .unwrap()
+                                .set_message(format!("! {} failed", request.action().as_str()));
+                            d.lock().unwrap().refresh();
+                        }
+                        Err(e) => log::warn!("Discarding CallError: {}", e),
+                    }
+                    continue;
+                }
+            };
             log::info!("Processing Response: {:?}", response);
-            match response.action.as_str() {
-                "BootNotification" => {
-                    let payload = serde_json::from_value::<
-                        rust_ocpp::v1_6::messages::boot_notification::BootNotificationResponse,
-                    >(response.payload)
-                    .unwrap();
+            let request = match pending.resolve(&response.unique_id) {
+                Ok(request) => request,
+                Err(e) => {
+                    log::warn!("Discarding response: {}", e);
+                    continue;
+                }
+            };
+            let payload = match ocpp::decode_response(&request.request, response.payload) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    log::error!(
+                        "Invalid {} response ({}): {}",
+                        request.action().as_str(),
+                        response.unique_id,
+                        e
+                    );
+                    continue;
+                }
+            };
+            match (&request.request, payload) {
+                (_, Response::BootNotification(payload)) => {
                     log::info!("BootNotificationResponse: {:?}", payload);
+                    if payload.status != RegistrationStatus::Accepted {
+                        d.lock()
+                            .unwrap()
+                            .set_message(format!("! Boot {:?}", payload.status));
+                        d.lock().unwrap().refresh();
+                    }
+                    clock::set_from_central_system(payload.current_time);
+                    if payload.status == RegistrationStatus::Accepted && payload.interval > 0 {
+                        configuration
+                            .lock()
+                            .unwrap()
+                            .change(HEARTBEAT_INTERVAL, &payload.interval.to_string());
+                    }
+                    registration.update(payload.status, payload.interval);
                 }
-                "Heartbeat" => {
-                    let payload = serde_json::from_value::<
-                        rust_ocpp::v1_6::messages::heart_beat::HeartbeatResponse,
-                    >(response.payload)
-                    .unwrap();
+                (_, Response::Heartbeat(payload)) => {
                     log::info!("HeartbeatResponse: {:?}", payload);
+                    clock::set_from_central_system(payload.current_time);
+                }
+                (Request::Authorize(authorize), Response::Authorize(payload)) => {
+                    log::info!("AuthorizeResponse: {:?}", payload);
+                    if configuration
+                        .lock()
+                        .unwrap()
+                        .boolean(AUTHORIZATION_CACHE_ENABLED)
+                    {
+                        remember_authorization(
+                            &local_list,
+                            &auth_cache,
+                            &authorize.id_tag,
+                            &payload.id_tag_info,
+                        );
+                    }
+                    let status = payload.id_tag_info.status;
+                    finish_authorization(
+                        &charger,
+                        &relay,
+                        &cable_lock,
+                        &meter,
+                        &send_queue,
+                        &unique_id,
+                        &authorize.id_tag,
+                        payload.id_tag_info.parent_id_tag.as_deref(),
+                        status.clone(),
+                    );
+                    if status != AuthorizationStatus::Accepted {
+                        d.lock().unwrap().set_message(format!("! {:?}", status));
+                        d.lock().unwrap().refresh();
+                        continue;
+                    }
                 }
-                _ => {
-                    log::info!("Unhandled response: {:?}", response);
+                (Request::StartTransaction(start), Response::StartTransaction(payload)) => {
+                    log::info!("StartTransactionResponse: {:?}", payload);
+                    if configuration
+                        .lock()
+                        .unwrap()
+                        .boolean(AUTHORIZATION_CACHE_ENABLED)
+                    {
+                        remember_authorization(
+                            &local_list,
+                            &auth_cache,
+                            &start.id_tag,
+                            &payload.id_tag_info,
+                        );
+                    }
+                    let status = payload.id_tag_info.status.clone();
+                    let energy = meter_register(&meter);
+                    let mut c = charger.lock().unwrap();
+                    let Some(evse) = c.evse_mut(start.connector_id) else {
+                        log::warn!("No EVSE for connector {}", start.connector_id);
+                        continue;
+                    };
+                    let mut stop =
+                        evse.transaction_started(payload.transaction_id, payload.id_tag_info);
+                    if stop.is_none() && status != AuthorizationStatus::Accepted {
+                        // the central system rejected the idTag, stop charging
+                        stop = evse.stop_session(Reason::DeAuthorized, energy);
+                        if let Ok((_, charger::ChargerOutput::Unlocked)) =
+                            c.transition(charger::ChargerInput::Unauthorized)
+                        {
+                            if let Err(e) = power_off(&relay, &cable_lock) {
+                                log::error!("Failed to stop charging: {}", e);
+                            }
+                        }
+                        d.lock().unwrap().set_message(format!("! {:?}", status));
+                        d.lock().unwrap().refresh();
+                    }
+                    if let Some(stop) = stop {
+                        send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                            unique_id.lock().unwrap().next_id().to_string(),
+                            stop,
+                        )));
+                    }
+                    if status != AuthorizationStatus::Accepted {
+                        continue;
+                    }
+                }
+                (_, Response::DataTransfer(payload)) => {
+                    log::info!("DataTransferResponse: {:?}", payload);
+                    data_transfer.resolve(&response.unique_id, Ok(payload));
+                }
+                (_, payload) => {
+                    log::info!("Unhandled response: {:?}", payload);
                 }
             }
             d.lock()
                 .unwrap()
-                .set_message(format!("<- {}", response.action));
+                .set_message(format!("<- {}", request.action().as_str()));
             d.lock().unwrap().refresh();
         }
         thread::sleep(Duration::from_millis(100));
     });
 
-    // Heartbeat thread
-    let unique_id = org_unique_id.clone();
+    // Pending request timeout thread
+    let d = display.clone();
+    let pending = org_pending.clone();
+    let charger = org_charger.clone();
+    let data_transfer = org_data_transfer.clone();
+    thread::spawn(move || loop {
+        for (unique_id, request) in pending.expired() {
+            log::error!(
+                "No response to {} ({}) within {:?}",
+                request.action().as_str(),
+                unique_id,
+                pending.timeout()
+            );
+            if request.action() == Action::Authorize {
+                let _ = charger
+                    .lock()
+                    .unwrap()
+                    .transition(charger::ChargerInput::Unauthorized);
+            }
+            if request.action() == Action::DataTransfer {
+                data_transfer.resolve(
+                    &unique_id,
+                    Err(anyhow::anyhow!(
+                        "No response within {:?}",
+                        pending.timeout()
+                    )),
+                );
+            }
+            if let Request::StartTransaction(start) = &request.request {
+                transaction_failed(&charger, start.connector_id);
+            }
+            d.lock()
+                .unwrap()
+                .set_message(format!("! {} timeout", request.action().as_str()));
+            d.lock().unwrap().refresh();
+        }
+        thread::sleep(Duration::from_secs(1));
+    });
+
+    // meter thread, samples the meter while charging
+    let charger = org_charger.clone();
+    let meter = org_meter.clone();
     let send_queue = org_command_queue_send.clone();
+    let unique_id = org_unique_id.clone();
+    let configuration = org_configuration.clone();
     thread::spawn(move || loop {
-        let command = commands::OCPPRequest {
-            message_type_id: commands::MessageType::Call,
-            unique_id: unique_id.lock().unwrap().next_id().to_string(),
-            action: "Heartbeat".to_string(),
-            payload: heartbeat_request().unwrap(),
+        // 0 disables sampling
+        let interval = configuration
+            .lock()
+            .unwrap()
+            .integer(METER_VALUE_SAMPLE_INTERVAL);
+        thread::sleep(Duration::from_secs(interval.max(1)));
+        if interval == 0 {
+            continue;
+        }
+        let transaction_id = {
+            let mut c = charger.lock().unwrap();
+            if c.get_state() != charger::State::Charging {
+                continue;
+            }
+            c.evse_mut(1).and_then(|evse| evse.transaction_id())
         };
-        send_queue.push(command);
+        match meter.lock().unwrap().read() {
+            Ok(reading) => send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.lock().unwrap().next_id().to_string(),
+                messages::meter_values_request(
+                    1,
+                    transaction_id,
+                    &reading,
+                    ReadingContext::SamplePeriodic,
+                ),
+            ))),
+            Err(e) => log::error!("Failed to read meter: {}", e),
+        }
+    });
 
-        thread::sleep(Duration::from_secs(60));
+    // Heartbeat thread, at the HeartbeatInterval set by the BootNotificationResponse or ChangeConfiguration
+    let unique_id = org_unique_id.clone();
+    let send_queue = org_command_queue_send.clone();
+    let registration = org_registration.clone();
+    let configuration = org_configuration.clone();
+    thread::spawn(move || loop {
+        let interval = configuration.lock().unwrap().integer(HEARTBEAT_INTERVAL);
+        thread::sleep(Duration::from_secs(interval));
+        if !registration.is_accepted() {
+            continue;
+        }
+        let command = OCPPRequest::new(
+            unique_id.lock().unwrap().next_id().to_string(),
+            heartbeat_request(),
+        );
+        send_queue.push(OCPPMessage::Call(command));
     });
 
     let d = display.clone();
@@ -368,3 +1686,181 @@ fn main() -> anyhow::Result<()> {
         disp.refresh();
     }
 }
+
+/// Drops the session of `connector_id` whose StartTransaction got a CallError or no response,
+/// so a session that already ended does not wait for a transaction id forever
+fn transaction_failed(charger: &Mutex<charger::Charger>, connector_id: u64) {
+    let mut c = charger.lock().unwrap();
+    let Some(evse) = c.evse_mut(connector_id) else {
+        return;
+    };
+    if evse.transaction_failed() {
+        log::warn!(
+            "Dropped the session on connector {} without a transaction id",
+            connector_id
+        );
+    }
+}
+
+/// Reads the Energy.Active.Import.Register in Wh for StartTransaction and StopTransaction
+fn meter_register(meter: &Mutex<Box<dyn MeterSource>>) -> i64 {
+    match meter.lock().unwrap().read() {
+        Ok(reading) => reading.energy as i64,
+        Err(e) => {
+            log::error!("Failed to read meter: {}", e);
+            0
+        }
+    }
+}
+
+/// Ends the authorization of a swipe, an Accepted idTag switches on the relay and starts a transaction
+fn finish_authorization(
+    charger: &Mutex<charger::Charger>,
+    relay: &Mutex<Relay>,
+    cable_lock: &Mutex<Option<Box<dyn CableLock>>>,
+    meter: &Mutex<Box<dyn MeterSource>>,
+    send_queue: &FifoQueue<OCPPMessage>,
+    unique_id: &Mutex<UniqueId>,
+    id_tag: &str,
+    parent_id_tag: Option<&str>,
+    status: AuthorizationStatus,
+) {
+    let energy = meter_register(meter);
+    let mut c = charger.lock().unwrap();
+    let reserved_for_other = c
+        .reservation()
+        .is_some_and(|reservation| !reservation.is_for(id_tag, parent_id_tag));
+    if reserved_for_other {
+        log::warn!("{} may not use the reservation", id_tag);
+    }
+    let input = if status == AuthorizationStatus::Accepted && !reserved_for_other {
+        charger::ChargerInput::Authorized
+    } else {
+        charger::ChargerInput::Unauthorized
+    };
+    match c.transition(input) {
+        Ok((_, charger::ChargerOutput::LockedAndPowerIsOn)) => {
+            if let Err(e) = power_on(relay, cable_lock) {
+                log::error!("Failed to start charging: {}", e);
+                let _ = c.transition(charger::ChargerInput::Unauthorized);
+                return;
+            }
+            let reservation_id = c.evse_mut(1).and_then(|evse| evse.start_session(id_tag));
+            send_queue.push(OCPPMessage::Call(OCPPRequest::new(
+                unique_id.lock().unwrap().next_id().to_string(),
+                messages::start_transaction_request(id_tag, energy, reservation_id),
+            )));
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Charger transition failed: {}", e),
+    }
+}
+
+/// Records the IdTagInfo the central system returned for `id_tag` in the authorization cache,
+/// idTags in the local authorization list are never cached
+fn remember_authorization(
+    local_list: &Mutex<LocalAuthList>,
+    auth_cache: &Mutex<AuthorizationCache>,
+    id_tag: &str,
+    info: &IdTagInfo,
+) {
+    if local_list.lock().unwrap().get(id_tag).is_none() {
+        auth_cache.lock().unwrap().update(id_tag, info.clone());
+    }
+}
+
+/// Locks the cable and switches on the relay, the relay stays off when the cable can't be locked
+fn power_on(
+    relay: &Mutex<Relay>,
+    cable_lock: &Mutex<Option<Box<dyn CableLock>>>,
+) -> anyhow::Result<()> {
+    if let Some(cable_lock) = cable_lock.lock().unwrap().as_mut() {
+        cable_lock.lock()?;
+    }
+    relay.lock().unwrap().set_high()?;
+    Ok(())
+}
+
+/// Switches off the relay and unlocks the cable
+fn power_off(
+    relay: &Mutex<Relay>,
+    cable_lock: &Mutex<Option<Box<dyn CableLock>>>,
+) -> anyhow::Result<()> {
+    relay.lock().unwrap().set_low()?;
+    if let Some(cable_lock) = cable_lock.lock().unwrap().as_mut() {
+        cable_lock.unlock()?;
+    }
+    Ok(())
+}
+
+/// The requests a TriggerMessage asks for, a StatusNotification without a connector is sent
+/// for the charger and its connector
+fn triggered_requests(
+    trigger: MessageTrigger,
+    connector_id: Option<u64>,
+    charger: &Mutex<charger::Charger>,
+    availability: &Mutex<Availability>,
+    meter: &Mutex<Box<dyn MeterSource>>,
+    firmware_status: &Mutex<FirmwareStatus>,
+    diagnostics_status: &Mutex<DiagnosticsStatus>,
+) -> Vec<Request> {
+    match trigger {
+        MessageTrigger::BootNotification => vec![messages::boot_notification_request().into()],
+        MessageTrigger::Heartbeat => vec![heartbeat_request().into()],
+        MessageTrigger::DiagnosticsStatusNotification => {
+            let status = diagnostics_status.lock().unwrap().clone();
+            vec![messages::diagnostics_status_notification_request(status).into()]
+        }
+        MessageTrigger::FirmwareStatusNotification => {
+            let status = firmware_status.lock().unwrap().clone();
+            vec![messages::firmware_status_notification_request(status).into()]
+        }
+        MessageTrigger::StatusNotification => {
+            let (state, finishing) = {
+                let c = charger.lock().unwrap();
+                (c.get_state(), c.finishing)
+            };
+            let charger_state = if availability.lock().unwrap().is_operative(0) {
+                charger::State::Available
+            } else {
+                charger::State::Unavailable
+            };
+            match connector_id {
+                Some(0) => {
+                    vec![messages::status_notification_request(0, &charger_state, false).into()]
+                }
+                Some(connector_id) => {
+                    vec![
+                        messages::status_notification_request(connector_id, &state, finishing)
+                            .into(),
+                    ]
+                }
+                None => vec![
+                    messages::status_notification_request(0, &charger_state, false).into(),
+                    messages::status_notification_request(1, &state, finishing).into(),
+                ],
+            }
+        }
+        MessageTrigger::MeterValues => {
+            let reading = match meter.lock().unwrap().read() {
+                Ok(reading) => reading,
+                Err(e) => {
+                    log::error!("Failed to read meter: {}", e);
+                    return Vec::new();
+                }
+            };
+            let transaction_id = charger
+                .lock()
+                .unwrap()
+                .evse_mut(1)
+                .and_then(|evse| evse.transaction_id());
+            vec![messages::meter_values_request(
+                connector_id.unwrap_or(1),
+                transaction_id,
+                &reading,
+                ReadingContext::Trigger,
+            )
+            .into()]
+        }
+    }
+}
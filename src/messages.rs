@@ -1,43 +1,169 @@
+use rust_ocpp::v1_6::messages::authorize::AuthorizeRequest;
+use rust_ocpp::v1_6::messages::boot_notification::BootNotificationRequest;
+use rust_ocpp::v1_6::messages::diagnostics_status_notification::DiagnosticsStatusNotificationRequest;
+use rust_ocpp::v1_6::messages::firmware_status_notification::FirmwareStatusNotificationRequest;
+use rust_ocpp::v1_6::messages::heart_beat::HeartbeatRequest;
+use rust_ocpp::v1_6::messages::meter_values::MeterValuesRequest;
+use rust_ocpp::v1_6::messages::start_transaction::StartTransactionRequest;
+use rust_ocpp::v1_6::messages::status_notification::StatusNotificationRequest;
+use rust_ocpp::v1_6::messages::stop_transaction::StopTransactionRequest;
+use rust_ocpp::v1_6::types::{
+    ChargePointErrorCode, ChargePointStatus, DiagnosticsStatus, FirmwareStatus, Measurand,
+    MeterValue, Phase, ReadingContext, Reason, SampledValue, UnitOfMeasure,
+};
+
+use crate::charger::State;
+use crate::clock;
 use crate::config::Config;
+use crate::meter::MeterReading;
 
-pub fn boot_notification_request() -> Result<serde_json::Value, serde_json::Error> {
-    let message = rust_ocpp::v1_6::messages::boot_notification::BootNotificationRequest {
+pub fn boot_notification_request() -> BootNotificationRequest {
+    BootNotificationRequest {
         charge_point_vendor: Config::default().charger.vendor,
         charge_point_model: Config::default().charger.model,
         charge_point_serial_number: Some(Config::default().charger.serial),
+        firmware_version: Some(env!("CARGO_PKG_VERSION").into()),
         ..Default::default()
-    };
-    let value = serde_json::to_value(message)?;
-    Ok(value)
+    }
+}
+
+pub fn heartbeat_request() -> HeartbeatRequest {
+    HeartbeatRequest {}
 }
 
-pub fn heartbeat_request() -> Result<serde_json::Value, serde_json::Error> {
-    let message = rust_ocpp::v1_6::messages::heart_beat::HeartbeatRequest {};
-    let value = serde_json::to_value(message)?;
-    Ok(value)
+pub fn authorize_request(id_tag: &str) -> AuthorizeRequest {
+    AuthorizeRequest {
+        id_tag: id_tag.into(),
+    }
 }
 
-pub fn start_transaction_request() -> Result<serde_json::Value, serde_json::Error> {
-    let message = rust_ocpp::v1_6::messages::start_transaction::StartTransactionRequest {
+/// Starts a transaction on connector 1, `reservation_id` is the reservation it uses up
+pub fn start_transaction_request(
+    id_tag: &str,
+    meter_start: i64,
+    reservation_id: Option<i64>,
+) -> StartTransactionRequest {
+    StartTransactionRequest {
         connector_id: 1,
-        id_tag: "123456".into(),
-        meter_start: 0,
-        timestamp: chrono::Utc::now(),
+        id_tag: id_tag.into(),
+        meter_start,
+        reservation_id,
+        timestamp: clock::now(),
+    }
+}
+
+pub fn stop_transaction_request(
+    id_tag: &str,
+    transaction_id: i64,
+    reason: Reason,
+    meter_stop: i64,
+) -> StopTransactionRequest {
+    StopTransactionRequest {
+        id_tag: Some(id_tag.into()),
+        meter_stop,
+        timestamp: clock::now(),
+        transaction_id,
+        reason: Some(reason),
         ..Default::default()
-    };
-    let value = serde_json::to_value(message)?;
-    Ok(value)
+    }
 }
 
-pub fn stop_transaction_request() -> Result<serde_json::Value, serde_json::Error> {
-    let message = rust_ocpp::v1_6::messages::stop_transaction::StopTransactionRequest {
-        id_tag: Some("123456".into()),
-        meter_stop: 0,
-        timestamp: chrono::Utc::now(),
-        transaction_id: 1,
-        reason: Some(rust_ocpp::v1_6::types::Reason::Local),
+/// Reports `state` for a connector, connector 0 reports the charger as a whole
+///
+/// `finishing` reports an Occupied connector whose transaction just ended as Finishing
+pub fn status_notification_request(
+    connector_id: u64,
+    state: &State,
+    finishing: bool,
+) -> StatusNotificationRequest {
+    let (status, error_code) = match state {
+        State::Available => (ChargePointStatus::Available, ChargePointErrorCode::NoError),
+        State::Reserved => (ChargePointStatus::Reserved, ChargePointErrorCode::NoError),
+        State::Occupied if finishing => {
+            (ChargePointStatus::Finishing, ChargePointErrorCode::NoError)
+        }
+        State::Occupied | State::Authorizing => {
+            (ChargePointStatus::Preparing, ChargePointErrorCode::NoError)
+        }
+        State::Charging => (ChargePointStatus::Charging, ChargePointErrorCode::NoError),
+        State::Error => (ChargePointStatus::Faulted, ChargePointErrorCode::OtherError),
+        State::Unavailable | State::Off => (
+            ChargePointStatus::Unavailable,
+            ChargePointErrorCode::NoError,
+        ),
+    };
+    StatusNotificationRequest {
+        connector_id,
+        error_code,
+        status,
+        timestamp: clock::is_trusted().then(clock::now),
         ..Default::default()
+    }
+}
+
+/// Reports a sample of the meter of a connector, `context` tells whether it is periodic or triggered
+pub fn meter_values_request(
+    connector_id: u64,
+    transaction_id: Option<i64>,
+    reading: &MeterReading,
+    context: ReadingContext,
+) -> MeterValuesRequest {
+    let sample = |value: f64, measurand: Measurand, unit: UnitOfMeasure, phase: Option<Phase>| {
+        SampledValue {
+            value: format!("{:.1}", value),
+            context: Some(context.clone()),
+            measurand: Some(measurand),
+            phase,
+            unit: Some(unit),
+            ..Default::default()
+        }
     };
-    let value = serde_json::to_value(message)?;
-    Ok(value)
+    let mut sampled_value = vec![
+        sample(
+            reading.energy,
+            Measurand::EnergyActiveImportRegister,
+            UnitOfMeasure::Wh,
+            None,
+        ),
+        sample(
+            reading.power,
+            Measurand::PowerActiveImport,
+            UnitOfMeasure::W,
+            None,
+        ),
+    ];
+    for (current, phase) in reading
+        .current
+        .iter()
+        .zip([Phase::L1, Phase::L2, Phase::L3])
+    {
+        sampled_value.push(sample(
+            *current,
+            Measurand::CurrentImport,
+            UnitOfMeasure::A,
+            Some(phase),
+        ));
+    }
+    MeterValuesRequest {
+        connector_id,
+        transaction_id,
+        meter_value: vec![MeterValue {
+            timestamp: clock::now(),
+            sampled_value,
+        }],
+    }
+}
+
+/// Reports the progress of a GetDiagnostics upload
+pub fn diagnostics_status_notification_request(
+    status: DiagnosticsStatus,
+) -> DiagnosticsStatusNotificationRequest {
+    DiagnosticsStatusNotificationRequest { status }
+}
+
+/// Reports the progress of an UpdateFirmware
+pub fn firmware_status_notification_request(
+    status: FirmwareStatus,
+) -> FirmwareStatusNotificationRequest {
+    FirmwareStatusNotificationRequest { status }
 }
@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+/// MeterReading
+/// A sample of the energy meter
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MeterReading {
+    /// Energy.Active.Import.Register in Wh
+    pub energy: f64,
+    /// Power.Active.Import in W
+    pub power: f64,
+    /// Current.Import in A, one value per phase starting at L1
+    pub current: Vec<f64>,
+}
+
+/// MeterSource
+/// Anything that can be sampled for energy, power and current
+pub trait MeterSource: Send {
+    fn read(&mut self) -> anyhow::Result<MeterReading>;
+}
+
+/// SimulatedMeter
+/// Draws up to a fixed current on every phase, limited to what `offered_current` returns
+///
+/// `offered_current` is called from `read`, so don't hold whatever it locks while reading the meter
+pub struct SimulatedMeter<F> {
+    current: f64,
+    voltage: f64,
+    phases: usize,
+    energy: f64,
+    last_read: Instant,
+    offered_current: F,
+}
+
+impl<F: Fn() -> f64 + Send> SimulatedMeter<F> {
+    pub fn new(current: f64, voltage: f64, phases: usize, offered_current: F) -> Self {
+        Self {
+            current,
+            voltage,
+            phases,
+            energy: 0.0,
+            last_read: Instant::now(),
+            offered_current,
+        }
+    }
+}
+
+impl<F: Fn() -> f64 + Send> MeterSource for SimulatedMeter<F> {
+    fn read(&mut self) -> anyhow::Result<MeterReading> {
+        let current = self.current.min((self.offered_current)()).max(0.0);
+        let power = current * self.voltage * self.phases as f64;
+        self.energy += power * self.last_read.elapsed().as_secs_f64() / 3600.0;
+        self.last_read = Instant::now();
+        Ok(MeterReading {
+            energy: self.energy,
+            power,
+            current: vec![current; self.phases],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn accumulates_energy_over_time() {
+        let mut meter = SimulatedMeter::new(16.0, 230.0, 3, || 16.0);
+        let first = meter.read().unwrap();
+        assert_eq!(first.power, 16.0 * 230.0 * 3.0);
+
+        thread::sleep(Duration::from_millis(200));
+        let second = meter.read().unwrap();
+        // 11040 W for at least 0.2 s
+        let expected = second.power * 0.2 / 3600.0;
+        assert!(second.energy - first.energy >= expected);
+        assert!(second.energy - first.energy < 2.0 * expected);
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(meter.read().unwrap().energy > second.energy);
+    }
+
+    #[test]
+    fn draws_at_most_the_offered_current() {
+        let offered = Arc::new(Mutex::new(6.0));
+        let limit = offered.clone();
+        let mut meter = SimulatedMeter::new(16.0, 230.0, 3, move || *limit.lock().unwrap());
+
+        let reading = meter.read().unwrap();
+        assert_eq!(reading.current, vec![6.0; 3]);
+        assert_eq!(reading.power, 6.0 * 230.0 * 3.0);
+
+        // never more than the rating of the connector
+        *offered.lock().unwrap() = 32.0;
+        assert_eq!(meter.read().unwrap().current, vec![16.0; 3]);
+    }
+
+    #[test]
+    fn draws_nothing_when_no_current_is_offered() {
+        let mut meter = SimulatedMeter::new(16.0, 230.0, 1, || 0.0);
+        let first = meter.read().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let second = meter.read().unwrap();
+        assert_eq!(second.current, vec![0.0]);
+        assert_eq!(second.power, 0.0);
+        assert_eq!(second.energy, first.energy);
+    }
+}
@@ -0,0 +1,193 @@
+use rust_ocpp::v1_6::messages::*;
+
+#[cfg(not(feature = "v2_0_1"))]
+mod v1_6;
+#[cfg(feature = "v2_0_1")]
+mod v2_0_1;
+
+#[cfg(not(feature = "v2_0_1"))]
+pub use v1_6::{
+    decode_request, decode_response, encode_request, encode_response, error_code, SUBPROTOCOL,
+};
+#[cfg(feature = "v2_0_1")]
+pub use v2_0_1::{
+    decode_request, decode_response, encode_request, encode_response, error_code, SUBPROTOCOL,
+};
+
+/// OCPPCall
+/// A request payload that can be sent as a Call, tied to its action and response payload
+pub trait OCPPCall: Into<Request> + Sized {
+    const ACTION: Action;
+    type Response: Into<Response>;
+
+    fn from_request(request: Request) -> Option<Self>;
+}
+
+/// OCPPCallResult
+/// A response payload that can be sent as a CallResult
+pub trait OCPPCallResult: Into<Response> + Sized {
+    fn from_response(response: Response) -> Option<Self>;
+}
+
+/// Generates the `Action`, `Request` and `Response` enums from a table of
+/// `Action => module::{Request, Response}` entries of `rust_ocpp::v1_6::messages`
+///
+/// These OCPP 1.6 messages are the model the firmware works with, the `encode_*`/`decode_*`
+/// functions map them to the protocol version selected at build time
+macro_rules! ocpp_actions {
+    ($($action:ident => $module:ident::{$request:ident, $response:ident}),* $(,)?) => {
+        /// Action
+        /// The OCPP 1.6 actions this charger supports
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($action),*
+        }
+
+        impl Action {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Action::$action => stringify!($action)),*
+                }
+            }
+        }
+
+        impl std::str::FromStr for Action {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> anyhow::Result<Self> {
+                match s {
+                    $(stringify!($action) => Ok(Action::$action),)*
+                    _ => Err(anyhow::anyhow!("{} is not a supported action", s)),
+                }
+            }
+        }
+
+        /// Request
+        /// The payload of a Call
+        #[derive(Debug, Clone)]
+        pub enum Request {
+            $($action($module::$request)),*
+        }
+
+        impl Request {
+            pub fn action(&self) -> Action {
+                match self {
+                    $(Request::$action(_) => Action::$action),*
+                }
+            }
+
+            pub fn to_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+                match self {
+                    $(Request::$action(payload) => serde_json::to_value(payload)),*
+                }
+            }
+
+            pub fn from_value(
+                action: Action,
+                payload: serde_json::Value,
+            ) -> Result<Self, serde_json::Error> {
+                match action {
+                    $(Action::$action => Ok(Request::$action(serde_json::from_value(payload)?))),*
+                }
+            }
+        }
+
+        /// Response
+        /// The payload of a CallResult
+        #[derive(Debug, Clone)]
+        pub enum Response {
+            $($action($module::$response)),*
+        }
+
+        impl Response {
+            pub fn action(&self) -> Action {
+                match self {
+                    $(Response::$action(_) => Action::$action),*
+                }
+            }
+
+            pub fn to_value(&self) -> Result<serde_json::Value, serde_json::Error> {
+                match self {
+                    $(Response::$action(payload) => serde_json::to_value(payload)),*
+                }
+            }
+
+            pub fn from_value(
+                action: Action,
+                payload: serde_json::Value,
+            ) -> Result<Self, serde_json::Error> {
+                match action {
+                    $(Action::$action => Ok(Response::$action(serde_json::from_value(payload)?))),*
+                }
+            }
+        }
+
+        $(
+            impl From<$module::$request> for Request {
+                fn from(payload: $module::$request) -> Self {
+                    Request::$action(payload)
+                }
+            }
+
+            impl From<$module::$response> for Response {
+                fn from(payload: $module::$response) -> Self {
+                    Response::$action(payload)
+                }
+            }
+
+            impl OCPPCall for $module::$request {
+                const ACTION: Action = Action::$action;
+                type Response = $module::$response;
+
+                fn from_request(request: Request) -> Option<Self> {
+                    match request {
+                        Request::$action(payload) => Some(payload),
+                        #[allow(unreachable_patterns)]
+                        _ => None,
+                    }
+                }
+            }
+
+            impl OCPPCallResult for $module::$response {
+                fn from_response(response: Response) -> Option<Self> {
+                    match response {
+                        Response::$action(payload) => Some(payload),
+                        #[allow(unreachable_patterns)]
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+ocpp_actions! {
+    BootNotification => boot_notification::{BootNotificationRequest, BootNotificationResponse},
+    Heartbeat => heart_beat::{HeartbeatRequest, HeartbeatResponse},
+    StartTransaction => start_transaction::{StartTransactionRequest, StartTransactionResponse},
+    StopTransaction => stop_transaction::{StopTransactionRequest, StopTransactionResponse},
+    RemoteStartTransaction => remote_start_transaction::{RemoteStartTransactionRequest, RemoteStartTransactionResponse},
+    RemoteStopTransaction => remote_stop_transaction::{RemoteStopTransactionRequest, RemoteStopTransactionResponse},
+    Authorize => authorize::{AuthorizeRequest, AuthorizeResponse},
+    StatusNotification => status_notification::{StatusNotificationRequest, StatusNotificationResponse},
+    MeterValues => meter_values::{MeterValuesRequest, MeterValuesResponse},
+    SendLocalList => send_local_list::{SendLocalListRequest, SendLocalListResponse},
+    GetLocalListVersion => get_local_list_version::{GetLocalListVersionRequest, GetLocalListVersionResponse},
+    ClearCache => clear_cache::{ClearCacheRequest, ClearCacheResponse},
+    GetConfiguration => get_configuration::{GetConfigurationRequest, GetConfigurationResponse},
+    ChangeConfiguration => change_configuration::{ChangeConfigurationRequest, ChangeConfigurationResponse},
+    Reset => reset::{ResetRequest, ResetResponse},
+    UnlockConnector => unlock_connector::{UnlockConnectorRequest, UnlockConnectorResponse},
+    ChangeAvailability => change_availability::{ChangeAvailabilityRequest, ChangeAvailabilityResponse},
+    SetChargingProfile => set_charging_profile::{SetChargingProfileRequest, SetChargingProfileResponse},
+    ClearChargingProfile => clear_charging_profile::{ClearChargingProfileRequest, ClearChargingProfileResponse},
+    GetCompositeSchedule => get_composite_schedule::{GetCompositeScheduleRequest, GetCompositeScheduleResponse},
+    TriggerMessage => trigger_message::{TriggerMessageRequest, TriggerMessageResponse},
+    ReserveNow => reserve_now::{ReserveNowRequest, ReserveNowResponse},
+    CancelReservation => cancel_reservation::{CancelReservationRequest, CancelReservationResponse},
+    DataTransfer => data_transfer::{DataTransferRequest, DataTransferResponse},
+    DiagnosticsStatusNotification => diagnostics_status_notification::{DiagnosticsStatusNotificationRequest, DiagnosticsStatusNotificationResponse},
+    FirmwareStatusNotification => firmware_status_notification::{FirmwareStatusNotificationRequest, FirmwareStatusNotificationResponse},
+    UpdateFirmware => update_firmware::{UpdateFirmwareRequest, UpdateFirmwareResponse},
+    GetDiagnostics => get_diagnostics::{GetDiagnosticsRequest, GetDiagnosticsResponse},
+}
@@ -0,0 +1,682 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rust_ocpp::v1_6::messages as v1_6;
+use rust_ocpp::v1_6::types::{
+    AuthorizationStatus, ChargePointStatus, IdTagInfo, MeterValue, Reason, RegistrationStatus,
+    RemoteStartStopStatus,
+};
+use rust_ocpp::v2_0_1::datatypes::charging_station_type::ChargingStationType;
+use rust_ocpp::v2_0_1::datatypes::evse_type::EVSEType;
+use rust_ocpp::v2_0_1::datatypes::id_token_info_type::IdTokenInfoType;
+use rust_ocpp::v2_0_1::datatypes::id_token_type::IdTokenType;
+use rust_ocpp::v2_0_1::datatypes::meter_value_type::MeterValueType;
+use rust_ocpp::v2_0_1::datatypes::sampled_value_type::SampledValueType;
+use rust_ocpp::v2_0_1::datatypes::transaction_type::TransactionType;
+use rust_ocpp::v2_0_1::datatypes::unit_of_measure_type::UnitOfMeasureType;
+use rust_ocpp::v2_0_1::enumerations::authorization_status_enum_type::AuthorizationStatusEnumType;
+use rust_ocpp::v2_0_1::enumerations::boot_reason_enum_type::BootReasonEnumType;
+use rust_ocpp::v2_0_1::enumerations::connector_status_enum_type::ConnectorStatusEnumType;
+use rust_ocpp::v2_0_1::enumerations::id_token_enum_type::IdTokenEnumType;
+use rust_ocpp::v2_0_1::enumerations::measurand_enum_type::MeasurandEnumType;
+use rust_ocpp::v2_0_1::enumerations::reading_context_enum_type::ReadingContextEnumType;
+use rust_ocpp::v2_0_1::enumerations::reason_enum_type::ReasonEnumType;
+use rust_ocpp::v2_0_1::enumerations::registration_status_enum_type::RegistrationStatusEnumType;
+use rust_ocpp::v2_0_1::enumerations::request_start_stop_status_enum_type::RequestStartStopStatusEnumType;
+use rust_ocpp::v2_0_1::enumerations::transaction_event_enum_type::TransactionEventEnumType;
+use rust_ocpp::v2_0_1::enumerations::trigger_reason_enum_type::TriggerReasonEnumType;
+use rust_ocpp::v2_0_1::messages::{
+    authorize, boot_notification, heartbeat, meter_values, request_start_transaction,
+    request_stop_transaction, status_notification, transaction_event,
+};
+
+use super::{Request, Response};
+use crate::clock;
+use crate::commands::{CallError, ErrorCode};
+
+/// OCPP-J subprotocol spoken by this build
+pub const SUBPROTOCOL: &str = "ocpp2.0.1";
+
+/// Last TransactionEvent sequence number sent per transaction id
+static SEQ_NOS: Mutex<BTreeMap<String, i64>> = Mutex::new(BTreeMap::new());
+
+/// idToken and remoteStartId of the last accepted RequestStartTransaction, its TransactionEvent
+/// Started has to echo the remoteStartId
+static REMOTE_START: Mutex<Option<(String, i64)>> = Mutex::new(None);
+
+/// Encodes a Request into the action name and payload of an OCPP 2.0.1 Call
+///
+/// StartTransaction and StopTransaction become TransactionEvent Started and Ended,
+/// the transaction id is chosen by the charger as the start timestamp in milliseconds.
+/// MeterValues of a transaction become TransactionEvent Updated. The Started event of a
+/// transaction requested with RequestStartTransaction carries its remoteStartId.
+pub fn encode_request(request: &Request) -> anyhow::Result<(&'static str, serde_json::Value)> {
+    match request {
+        Request::BootNotification(request) => Ok((
+            "BootNotification",
+            serde_json::to_value(boot_notification::BootNotificationRequest {
+                reason: BootReasonEnumType::PowerUp,
+                charging_station: ChargingStationType {
+                    serial_number: request.charge_point_serial_number.clone(),
+                    model: request.charge_point_model.clone(),
+                    vendor_name: request.charge_point_vendor.clone(),
+                    firmware_version: request.firmware_version.clone(),
+                    modem: None,
+                },
+            })?,
+        )),
+        Request::Heartbeat(_) => Ok((
+            "Heartbeat",
+            serde_json::to_value(heartbeat::HeartbeatRequest {})?,
+        )),
+        Request::StartTransaction(request) => {
+            let transaction_id = start_transaction_id(request).to_string();
+            let remote_start_id = {
+                let mut remote_start = REMOTE_START.lock().unwrap();
+                if remote_start
+                    .as_ref()
+                    .is_some_and(|(id_tag, _)| *id_tag == request.id_tag)
+                {
+                    remote_start
+                        .take()
+                        .map(|(_, remote_start_id)| remote_start_id)
+                } else {
+                    None
+                }
+            };
+            Ok((
+                "TransactionEvent",
+                serde_json::to_value(transaction_event::TransactionEventRequest {
+                    event_type: TransactionEventEnumType::Started,
+                    timestamp: request.timestamp,
+                    trigger_reason: match remote_start_id {
+                        Some(_) => TriggerReasonEnumType::RemoteStart,
+                        None => TriggerReasonEnumType::Authorized,
+                    },
+                    seq_no: next_seq_no(&transaction_id, false),
+                    reservation_id: request.reservation_id,
+                    transaction_info: TransactionType {
+                        transaction_id,
+                        remote_start_id,
+                        ..Default::default()
+                    },
+                    id_token: Some(id_token(&request.id_tag)),
+                    evse: Some(evse(request.connector_id)),
+                    meter_value: Some(vec![energy_meter_value(
+                        request.timestamp,
+                        request.meter_start,
+                        ReadingContextEnumType::TransactionBegin,
+                    )]),
+                    ..Default::default()
+                })?,
+            ))
+        }
+        Request::StopTransaction(request) => {
+            let transaction_id = request.transaction_id.to_string();
+            let reason = request.reason.clone().unwrap_or(Reason::Local);
+            Ok((
+                "TransactionEvent",
+                serde_json::to_value(transaction_event::TransactionEventRequest {
+                    event_type: TransactionEventEnumType::Ended,
+                    timestamp: request.timestamp,
+                    trigger_reason: trigger_reason(&reason),
+                    seq_no: next_seq_no(&transaction_id, true),
+                    transaction_info: TransactionType {
+                        transaction_id,
+                        stopped_reason: Some(stopped_reason(&reason)),
+                        ..Default::default()
+                    },
+                    id_token: request.id_tag.as_deref().map(id_token),
+                    meter_value: Some(vec![energy_meter_value(
+                        request.timestamp,
+                        request.meter_stop,
+                        ReadingContextEnumType::TransactionEnd,
+                    )]),
+                    ..Default::default()
+                })?,
+            ))
+        }
+        Request::StatusNotification(request) => {
+            if request.connector_id == 0 {
+                anyhow::bail!("OCPP 2.0.1 has no StatusNotification for the whole charger");
+            }
+            Ok((
+                "StatusNotification",
+                serde_json::to_value(status_notification::StatusNotificationRequest {
+                    timestamp: request.timestamp.unwrap_or_else(clock::now),
+                    connector_status: connector_status(&request.status),
+                    evse_id: request.connector_id as i64,
+                    connector_id: 1,
+                })?,
+            ))
+        }
+        Request::MeterValues(request) => {
+            let meter_value = request
+                .meter_value
+                .iter()
+                .map(meter_value)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            match request.transaction_id {
+                Some(transaction_id) => {
+                    let transaction_id = transaction_id.to_string();
+                    Ok((
+                        "TransactionEvent",
+                        serde_json::to_value(transaction_event::TransactionEventRequest {
+                            event_type: TransactionEventEnumType::Updated,
+                            timestamp: meter_value
+                                .first()
+                                .map_or_else(clock::now, |value| value.timestamp),
+                            trigger_reason: TriggerReasonEnumType::MeterValuePeriodic,
+                            seq_no: next_seq_no(&transaction_id, false),
+                            transaction_info: TransactionType {
+                                transaction_id,
+                                ..Default::default()
+                            },
+                            evse: Some(evse(request.connector_id)),
+                            meter_value: Some(meter_value),
+                            ..Default::default()
+                        })?,
+                    ))
+                }
+                None => Ok((
+                    "MeterValues",
+                    serde_json::to_value(meter_values::MeterValuesRequest {
+                        evse_id: request.connector_id as i64,
+                        meter_value,
+                    })?,
+                )),
+            }
+        }
+        Request::Authorize(request) => Ok((
+            "Authorize",
+            serde_json::to_value(authorize::AuthorizeRequest {
+                id_token: id_token(&request.id_tag),
+                ..Default::default()
+            })?,
+        )),
+        request => anyhow::bail!(
+            "{} is not supported over OCPP 2.0.1",
+            request.action().as_str()
+        ),
+    }
+}
+
+/// Decodes the action name and payload of an OCPP 2.0.1 Call from the central system
+pub fn decode_request(action: &str, payload: serde_json::Value) -> anyhow::Result<Request> {
+    match action {
+        "RequestStartTransaction" => {
+            let request = serde_json::from_value::<
+                request_start_transaction::RequestStartTransactionRequest,
+            >(payload)?;
+            *REMOTE_START.lock().unwrap() =
+                Some((request.id_token.id_token.clone(), request.remote_start_id));
+            Ok(Request::RemoteStartTransaction(
+                v1_6::remote_start_transaction::RemoteStartTransactionRequest {
+                    connector_id: request.evse_id.map(|evse_id| evse_id as u64),
+                    id_tag: request.id_token.id_token,
+                    charging_profile: None,
+                },
+            ))
+        }
+        "RequestStopTransaction" => {
+            let request = serde_json::from_value::<
+                request_stop_transaction::RequestStopTransactionRequest,
+            >(payload)?;
+            let transaction_id = request.transaction_id.parse::<i64>().map_err(|_| {
+                CallError::new(
+                    "",
+                    ErrorCode::PropertyConstraintViolation,
+                    &format!("Unknown transaction {}", request.transaction_id),
+                )
+            })?;
+            Ok(Request::RemoteStopTransaction(
+                v1_6::remote_stop_transaction::RemoteStopTransactionRequest { transaction_id },
+            ))
+        }
+        _ => Err(CallError::new(
+            "",
+            ErrorCode::NotImplemented,
+            &format!("{} is not a supported action", action),
+        )
+        .into()),
+    }
+}
+
+/// Encodes a Response into the payload of an OCPP 2.0.1 CallResult
+pub fn encode_response(response: &Response) -> anyhow::Result<serde_json::Value> {
+    match response {
+        Response::RemoteStartTransaction(response) => {
+            if response.status == RemoteStartStopStatus::Rejected {
+                REMOTE_START.lock().unwrap().take();
+            }
+            Ok(serde_json::to_value(
+                request_start_transaction::RequestStartTransactionResponse {
+                    status: request_start_stop_status(&response.status),
+                    ..Default::default()
+                },
+            )?)
+        }
+        Response::RemoteStopTransaction(response) => Ok(serde_json::to_value(
+            request_stop_transaction::RequestStopTransactionResponse {
+                status: request_start_stop_status(&response.status),
+                ..Default::default()
+            },
+        )?),
+        response => anyhow::bail!(
+            "{} is not supported over OCPP 2.0.1",
+            response.action().as_str()
+        ),
+    }
+}
+
+/// Decodes the payload of an OCPP 2.0.1 CallResult to the Call `request`
+pub fn decode_response(request: &Request, payload: serde_json::Value) -> anyhow::Result<Response> {
+    match request {
+        Request::BootNotification(_) => {
+            let response =
+                serde_json::from_value::<boot_notification::BootNotificationResponse>(payload)?;
+            Ok(Response::BootNotification(
+                v1_6::boot_notification::BootNotificationResponse {
+                    current_time: response.current_time,
+                    interval: response.interval as u32,
+                    status: match response.status {
+                        RegistrationStatusEnumType::Accepted => RegistrationStatus::Accepted,
+                        RegistrationStatusEnumType::Pending => RegistrationStatus::Pending,
+                        RegistrationStatusEnumType::Rejected => RegistrationStatus::Rejected,
+                    },
+                },
+            ))
+        }
+        Request::Heartbeat(_) => {
+            let response = serde_json::from_value::<heartbeat::HeartbeatResponse>(payload)?;
+            Ok(Response::Heartbeat(v1_6::heart_beat::HeartbeatResponse {
+                current_time: response.current_time,
+            }))
+        }
+        Request::StartTransaction(request) => {
+            let response =
+                serde_json::from_value::<transaction_event::TransactionEventResponse>(payload)?;
+            Ok(Response::StartTransaction(
+                v1_6::start_transaction::StartTransactionResponse {
+                    id_tag_info: response.id_token_info.map(id_tag_info).unwrap_or_default(),
+                    transaction_id: start_transaction_id(request),
+                },
+            ))
+        }
+        Request::StopTransaction(_) => {
+            let response =
+                serde_json::from_value::<transaction_event::TransactionEventResponse>(payload)?;
+            Ok(Response::StopTransaction(
+                v1_6::stop_transaction::StopTransactionResponse {
+                    id_tag_info: response.id_token_info.map(id_tag_info),
+                },
+            ))
+        }
+        Request::StatusNotification(_) => {
+            serde_json::from_value::<status_notification::StatusNotificationResponse>(payload)?;
+            Ok(Response::StatusNotification(
+                v1_6::status_notification::StatusNotificationResponse {},
+            ))
+        }
+        Request::MeterValues(request) => {
+            if request.transaction_id.is_some() {
+                serde_json::from_value::<transaction_event::TransactionEventResponse>(payload)?;
+            } else {
+                serde_json::from_value::<meter_values::MeterValuesResponse>(payload)?;
+            }
+            Ok(Response::MeterValues(
+                v1_6::meter_values::MeterValuesResponse {},
+            ))
+        }
+        Request::Authorize(_) => {
+            let response = serde_json::from_value::<authorize::AuthorizeResponse>(payload)?;
+            Ok(Response::Authorize(v1_6::authorize::AuthorizeResponse {
+                id_tag_info: id_tag_info(response.id_token_info),
+            }))
+        }
+        request => anyhow::bail!(
+            "{} is not supported over OCPP 2.0.1",
+            request.action().as_str()
+        ),
+    }
+}
+
+/// The name of an error code in an OCPP 2.0.1 CallError, two of them are spelled differently
+pub fn error_code(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::FormationViolation => "FormatViolation",
+        ErrorCode::OccurenceConstraintViolation => "OccurrenceConstraintViolation",
+        code => code.as_str(),
+    }
+}
+
+fn start_transaction_id(request: &v1_6::start_transaction::StartTransactionRequest) -> i64 {
+    request.timestamp.timestamp_millis()
+}
+
+fn next_seq_no(transaction_id: &str, ended: bool) -> i64 {
+    let mut seq_nos = SEQ_NOS.lock().unwrap();
+    let seq_no = seq_nos.get(transaction_id).map_or(0, |seq_no| seq_no + 1);
+    if ended {
+        seq_nos.remove(transaction_id);
+    } else {
+        seq_nos.insert(transaction_id.to_string(), seq_no);
+    }
+    seq_no
+}
+
+fn id_token(id_tag: &str) -> IdTokenType {
+    IdTokenType {
+        id_token: id_tag.to_string(),
+        kind: IdTokenEnumType::ISO14443,
+        additional_info: None,
+    }
+}
+
+fn evse(connector_id: u64) -> EVSEType {
+    EVSEType {
+        id: connector_id as i64,
+        connector_id: Some(1),
+    }
+}
+
+fn energy_meter_value(
+    timestamp: DateTime<Utc>,
+    energy: i64,
+    context: ReadingContextEnumType,
+) -> MeterValueType {
+    MeterValueType {
+        timestamp,
+        sampled_value: vec![SampledValueType {
+            value: energy as f64,
+            context: Some(context),
+            measurand: Some(MeasurandEnumType::EnergyActiveImportRegister),
+            ..Default::default()
+        }],
+    }
+}
+
+/// Measurand, phase, context and location share their names between both versions
+fn meter_value(value: &MeterValue) -> anyhow::Result<MeterValueType> {
+    let sampled_value = value
+        .sampled_value
+        .iter()
+        .map(|sample| {
+            Ok(SampledValueType {
+                value: sample.value.parse()?,
+                context: convert(&sample.context)?,
+                measurand: convert(&sample.measurand)?,
+                phase: convert(&sample.phase)?,
+                location: convert(&sample.location)?,
+                unit_of_measure: sample
+                    .unit
+                    .as_ref()
+                    .map(|unit| -> anyhow::Result<UnitOfMeasureType> {
+                        Ok(UnitOfMeasureType {
+                            unit: serde_json::to_value(unit)?.as_str().map(str::to_string),
+                            multiplier: None,
+                        })
+                    })
+                    .transpose()?,
+                ..Default::default()
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(MeterValueType {
+        timestamp: value.timestamp,
+        sampled_value,
+    })
+}
+
+fn convert<T: serde::Serialize, U: serde::de::DeserializeOwned>(value: &T) -> anyhow::Result<U> {
+    Ok(serde_json::from_value(serde_json::to_value(value)?)?)
+}
+
+fn id_tag_info(info: IdTokenInfoType) -> IdTagInfo {
+    IdTagInfo {
+        expiry_date: info.cache_expiry_date_time,
+        parent_id_tag: info.group_id_token.map(|token| token.id_token),
+        status: match info.status {
+            AuthorizationStatusEnumType::Accepted => AuthorizationStatus::Accepted,
+            AuthorizationStatusEnumType::Blocked => AuthorizationStatus::Blocked,
+            AuthorizationStatusEnumType::ConcurrentTx => AuthorizationStatus::ConcurrentTx,
+            AuthorizationStatusEnumType::Expired => AuthorizationStatus::Expired,
+            _ => AuthorizationStatus::Invalid,
+        },
+    }
+}
+
+fn connector_status(status: &ChargePointStatus) -> ConnectorStatusEnumType {
+    match status {
+        ChargePointStatus::Available => ConnectorStatusEnumType::Available,
+        ChargePointStatus::Reserved => ConnectorStatusEnumType::Reserved,
+        ChargePointStatus::Unavailable => ConnectorStatusEnumType::Unavailable,
+        ChargePointStatus::Faulted => ConnectorStatusEnumType::Faulted,
+        _ => ConnectorStatusEnumType::Occupied,
+    }
+}
+
+fn trigger_reason(reason: &Reason) -> TriggerReasonEnumType {
+    match reason {
+        Reason::Remote => TriggerReasonEnumType::RemoteStop,
+        Reason::EVDisconnected => TriggerReasonEnumType::EVDeparted,
+        Reason::DeAuthorized => TriggerReasonEnumType::Deauthorized,
+        Reason::HardReset | Reason::SoftReset => TriggerReasonEnumType::ResetCommand,
+        Reason::UnlockCommand => TriggerReasonEnumType::UnlockCommand,
+        Reason::Local => TriggerReasonEnumType::StopAuthorized,
+        _ => TriggerReasonEnumType::AbnormalCondition,
+    }
+}
+
+fn stopped_reason(reason: &Reason) -> ReasonEnumType {
+    match reason {
+        Reason::DeAuthorized => ReasonEnumType::DeAuthorized,
+        Reason::EmergencyStop => ReasonEnumType::EmergencyStop,
+        Reason::EVDisconnected => ReasonEnumType::EVDisconnected,
+        Reason::HardReset => ReasonEnumType::ImmediateReset,
+        Reason::Local => ReasonEnumType::Local,
+        Reason::PowerLoss => ReasonEnumType::PowerLoss,
+        Reason::Reboot | Reason::SoftReset => ReasonEnumType::Reboot,
+        Reason::Remote => ReasonEnumType::Remote,
+        Reason::Other | Reason::UnlockCommand => ReasonEnumType::Other,
+    }
+}
+
+fn request_start_stop_status(status: &RemoteStartStopStatus) -> RequestStartStopStatusEnumType {
+    match status {
+        RemoteStartStopStatus::Accepted => RequestStartStopStatusEnumType::Accepted,
+        RemoteStartStopStatus::Rejected => RequestStartStopStatusEnumType::Rejected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rust_ocpp::v1_6::types::ReadingContext;
+    use serde_json::json;
+
+    use super::*;
+    use crate::messages;
+    use crate::meter::MeterReading;
+
+    /// Each test uses its own start time, so its transaction id and seqNos are its own
+    fn start_transaction(
+        id_tag: &str,
+        second: u32,
+    ) -> v1_6::start_transaction::StartTransactionRequest {
+        let mut request = messages::start_transaction_request(id_tag, 1000, None);
+        request.timestamp = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap();
+        request
+    }
+
+    fn encode(request: impl Into<Request>) -> (&'static str, serde_json::Value) {
+        encode_request(&request.into()).unwrap()
+    }
+
+    #[test]
+    fn a_transaction_is_a_series_of_transaction_events() {
+        let start = start_transaction("tag", 1);
+        let transaction_id = start.timestamp.timestamp_millis();
+
+        let (action, payload) = encode(start.clone());
+        assert_eq!(action, "TransactionEvent");
+        assert_eq!(payload["eventType"], "Started");
+        assert_eq!(payload["triggerReason"], "Authorized");
+        assert_eq!(payload["seqNo"], 0);
+        assert_eq!(
+            payload["transactionInfo"]["transactionId"],
+            transaction_id.to_string()
+        );
+        assert_eq!(payload["idToken"]["idToken"], "tag");
+        assert_eq!(payload["meterValue"][0]["sampledValue"][0]["value"], 1000.0);
+
+        // the central system doesn't assign the transaction id, the charger did
+        let response = decode_response(
+            &start.clone().into(),
+            json!({"idTokenInfo": {"status": "Accepted"}}),
+        )
+        .unwrap();
+        let Response::StartTransaction(response) = response else {
+            panic!("{:?} is not a StartTransactionResponse", response);
+        };
+        assert_eq!(response.transaction_id, transaction_id);
+        assert_eq!(response.id_tag_info.status, AuthorizationStatus::Accepted);
+
+        let reading = MeterReading {
+            energy: 1500.0,
+            power: 3680.0,
+            current: vec![16.0],
+        };
+        let (action, payload) = encode(messages::meter_values_request(
+            1,
+            Some(transaction_id),
+            &reading,
+            ReadingContext::SamplePeriodic,
+        ));
+        assert_eq!(action, "TransactionEvent");
+        assert_eq!(payload["eventType"], "Updated");
+        assert_eq!(payload["triggerReason"], "MeterValuePeriodic");
+        assert_eq!(payload["seqNo"], 1);
+
+        let stop = messages::stop_transaction_request("tag", transaction_id, Reason::Remote, 2000);
+        let (action, payload) = encode(stop);
+        assert_eq!(action, "TransactionEvent");
+        assert_eq!(payload["eventType"], "Ended");
+        assert_eq!(payload["triggerReason"], "RemoteStop");
+        assert_eq!(payload["seqNo"], 2);
+        assert_eq!(payload["transactionInfo"]["stoppedReason"], "Remote");
+
+        // the seqNos of an ended transaction are forgotten
+        assert!(SEQ_NOS
+            .lock()
+            .unwrap()
+            .get(&transaction_id.to_string())
+            .is_none());
+    }
+
+    #[test]
+    fn meter_values_outside_a_transaction_stay_meter_values() {
+        let reading = MeterReading {
+            energy: 1500.0,
+            power: 0.0,
+            current: vec![0.0],
+        };
+        let (action, payload) = encode(messages::meter_values_request(
+            1,
+            None,
+            &reading,
+            ReadingContext::SamplePeriodic,
+        ));
+        assert_eq!(action, "MeterValues");
+        assert_eq!(payload["evseId"], 1);
+    }
+
+    #[test]
+    fn a_remote_start_echoes_its_remote_start_id() {
+        let request = decode_request(
+            "RequestStartTransaction",
+            json!({"evseId": 1, "remoteStartId": 7, "idToken": {"idToken": "remote", "type": "ISO14443"}}),
+        )
+        .unwrap();
+        let Request::RemoteStartTransaction(request) = request else {
+            panic!("{:?} is not a RemoteStartTransaction", request);
+        };
+        assert_eq!(request.connector_id, Some(1));
+        assert_eq!(request.id_tag, "remote");
+
+        let (_, payload) = encode(start_transaction("remote", 2));
+        assert_eq!(payload["triggerReason"], "RemoteStart");
+        assert_eq!(payload["transactionInfo"]["remoteStartId"], 7);
+
+        // a rejected remote start doesn't start a transaction to echo it in
+        decode_request(
+            "RequestStartTransaction",
+            json!({"remoteStartId": 8, "idToken": {"idToken": "remote", "type": "ISO14443"}}),
+        )
+        .unwrap();
+        let rejected = v1_6::remote_start_transaction::RemoteStartTransactionResponse {
+            status: RemoteStartStopStatus::Rejected,
+        };
+        let payload = encode_response(&rejected.into()).unwrap();
+        assert_eq!(payload["status"], "Rejected");
+        let (_, payload) = encode(start_transaction("remote", 3));
+        assert_eq!(payload["triggerReason"], "Authorized");
+        assert!(payload["transactionInfo"].get("remoteStartId").is_none());
+    }
+
+    #[test]
+    fn a_remote_stop_needs_a_transaction_of_this_charger() {
+        let request =
+            decode_request("RequestStopTransaction", json!({"transactionId": "42"})).unwrap();
+        assert!(matches!(
+            request,
+            Request::RemoteStopTransaction(request) if request.transaction_id == 42
+        ));
+
+        let error = decode_request("RequestStopTransaction", json!({"transactionId": "abc"}))
+            .unwrap_err()
+            .downcast::<CallError>()
+            .unwrap();
+        assert_eq!(error.error_code, ErrorCode::PropertyConstraintViolation);
+
+        let error = decode_request("Reset", json!({"type": "Immediate"}))
+            .unwrap_err()
+            .downcast::<CallError>()
+            .unwrap();
+        assert_eq!(error.error_code, ErrorCode::NotImplemented);
+    }
+
+    #[test]
+    fn maps_the_boot_notification() {
+        let (action, payload) = encode(messages::boot_notification_request());
+        assert_eq!(action, "BootNotification");
+        assert_eq!(payload["reason"], "PowerUp");
+        assert!(payload["chargingStation"]["model"].is_string());
+
+        let response = decode_response(
+            &messages::boot_notification_request().into(),
+            json!({"currentTime": "2024-01-01T00:00:00Z", "interval": 300, "status": "Pending"}),
+        )
+        .unwrap();
+        let Response::BootNotification(response) = response else {
+            panic!("{:?} is not a BootNotificationResponse", response);
+        };
+        assert_eq!(response.interval, 300);
+        assert_eq!(response.status, RegistrationStatus::Pending);
+    }
+
+    #[test]
+    fn spells_error_codes_the_2_0_1_way() {
+        assert_eq!(error_code(ErrorCode::FormationViolation), "FormatViolation");
+        assert_eq!(
+            error_code(ErrorCode::OccurenceConstraintViolation),
+            "OccurrenceConstraintViolation"
+        );
+        assert_eq!(error_code(ErrorCode::NotImplemented), "NotImplemented");
+        let frame = CallError::new("1", ErrorCode::FormationViolation, "")
+            .to_ocpp_json_message()
+            .unwrap();
+        assert_eq!(frame, r#"[4,"1","FormatViolation","",{}]"#);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::commands::OCPPRequest;
+use crate::ocpp::{Action, Request};
+
+/// PendingRequest
+/// A Call that was sent to the central system and is waiting for its CallResult
+#[derive(Debug, Clone)]
+pub struct PendingRequest {
+    pub request: Request,
+    pub sent_at: Instant,
+}
+
+impl PendingRequest {
+    pub fn action(&self) -> Action {
+        self.request.action()
+    }
+}
+
+/// PendingRequests
+/// Outstanding Calls keyed by the unique id they were sent with
+pub struct PendingRequests {
+    requests: Mutex<HashMap<String, PendingRequest>>,
+    timeout: Duration,
+}
+
+impl PendingRequests {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            requests: Mutex::new(HashMap::new()),
+            timeout,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.lock().unwrap().is_empty()
+    }
+
+    /// Whether a Call of `action` is waiting for its response
+    pub fn contains(&self, action: Action) -> bool {
+        self.requests
+            .lock()
+            .unwrap()
+            .values()
+            .any(|request| request.action() == action)
+    }
+
+    /// Registers a Call that has just been sent
+    pub fn register(&self, request: &OCPPRequest) {
+        self.requests.lock().unwrap().insert(
+            request.unique_id.clone(),
+            PendingRequest {
+                request: request.payload.clone(),
+                sent_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Removes and returns the Call a response with `unique_id` belongs to
+    ///
+    /// # Arguments
+    ///
+    /// * `unique_id` - the unique id of the received CallResult
+    ///
+    /// # Returns
+    ///
+    /// Result<PendingRequest> - the originating Call, or an error when the id is unknown or has timed out
+    ///
+    pub fn resolve(&self, unique_id: &str) -> anyhow::Result<PendingRequest> {
+        let request = self
+            .requests
+            .lock()
+            .unwrap()
+            .remove(unique_id)
+            .ok_or_else(|| anyhow::anyhow!("No pending request with unique id {}", unique_id))?;
+        if request.sent_at.elapsed() > self.timeout {
+            anyhow::bail!(
+                "Response to {} ({}) arrived after {:?}",
+                request.action().as_str(),
+                unique_id,
+                self.timeout
+            );
+        }
+        Ok(request)
+    }
+
+    /// Removes and returns all Calls that have been waiting longer than the timeout
+    pub fn expired(&self) -> Vec<(String, PendingRequest)> {
+        let mut requests = self.requests.lock().unwrap();
+        let expired: Vec<String> = requests
+            .iter()
+            .filter(|(_, request)| request.sent_at.elapsed() > self.timeout)
+            .map(|(unique_id, _)| unique_id.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|unique_id| {
+                requests
+                    .remove(&unique_id)
+                    .map(|request| (unique_id, request))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::messages::{boot_notification_request, heartbeat_request};
+
+    #[test]
+    fn a_response_resolves_its_call() {
+        let pending = PendingRequests::new(Duration::from_secs(60));
+        assert!(pending.is_empty());
+        pending.register(&OCPPRequest::new("1".into(), heartbeat_request()));
+        pending.register(&OCPPRequest::new("2".into(), boot_notification_request()));
+        assert!(pending.contains(Action::Heartbeat));
+        assert!(pending.contains(Action::BootNotification));
+
+        assert_eq!(
+            pending.resolve("2").unwrap().action(),
+            Action::BootNotification
+        );
+        assert!(!pending.contains(Action::BootNotification));
+        assert!(pending.contains(Action::Heartbeat));
+        // only once
+        assert!(pending.resolve("2").is_err());
+        assert!(pending.resolve("unknown").is_err());
+
+        assert_eq!(pending.resolve("1").unwrap().action(), Action::Heartbeat);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn a_late_response_is_an_error() {
+        let pending = PendingRequests::new(Duration::from_millis(10));
+        pending.register(&OCPPRequest::new("1".into(), heartbeat_request()));
+        thread::sleep(Duration::from_millis(20));
+        assert!(pending.resolve("1").is_err());
+        // and is no longer pending
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn expired_removes_the_calls_past_the_timeout() {
+        let pending = PendingRequests::new(Duration::from_millis(50));
+        pending.register(&OCPPRequest::new("1".into(), heartbeat_request()));
+        assert!(pending.expired().is_empty());
+        thread::sleep(Duration::from_millis(60));
+        pending.register(&OCPPRequest::new("2".into(), boot_notification_request()));
+
+        let expired = pending.expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, "1");
+        assert_eq!(expired[0].1.action(), Action::Heartbeat);
+        assert!(!pending.contains(Action::Heartbeat));
+        assert!(pending.expired().is_empty());
+        assert!(pending.resolve("2").is_ok());
+    }
+}
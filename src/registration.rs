@@ -0,0 +1,57 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rust_ocpp::v1_6::types::RegistrationStatus;
+
+struct State {
+    status: Option<RegistrationStatus>,
+    interval: Duration,
+    updates: u32,
+}
+
+/// Registration
+/// The outcome of the last BootNotification, nothing but BootNotification may be sent until it is Accepted
+pub struct Registration {
+    state: Mutex<State>,
+}
+
+impl Registration {
+    /// `interval` is used until the central system sends one
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            state: Mutex::new(State {
+                status: None,
+                interval,
+                updates: 0,
+            }),
+        }
+    }
+
+    pub fn status(&self) -> Option<RegistrationStatus> {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.status() == Some(RegistrationStatus::Accepted)
+    }
+
+    /// The heartbeat interval once accepted, otherwise the time to wait before retrying the BootNotification
+    pub fn interval(&self) -> Duration {
+        self.state.lock().unwrap().interval
+    }
+
+    /// Number of BootNotificationResponses received, to tell when a new one arrived
+    pub fn updates(&self) -> u32 {
+        self.state.lock().unwrap().updates
+    }
+
+    /// Records a BootNotificationResponse, an interval of 0 keeps the current one
+    pub fn update(&self, status: RegistrationStatus, interval: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.status = Some(status);
+        if interval > 0 {
+            state.interval = Duration::from_secs(interval as u64);
+        }
+        state.updates += 1;
+    }
+}
@@ -0,0 +1,663 @@
+use chrono::{DateTime, Duration, Utc};
+use rust_ocpp::v1_6::messages::clear_charging_profile::ClearChargingProfileRequest;
+use rust_ocpp::v1_6::types::{
+    ChargingProfile, ChargingProfileKindType, ChargingProfilePurposeType, ChargingProfileStatus,
+    ChargingRateUnitType, ChargingSchedule, ChargingSchedulePeriod, ClearChargingProfileStatus,
+    RecurrencyKindType,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::evse::Session;
+use crate::storage::Storage;
+
+/// Highest stack level of a profile (ChargeProfileMaxStackLevel)
+pub const MAX_STACK_LEVEL: u64 = 10;
+/// Maximum number of periods in a schedule (ChargingScheduleMaxPeriods)
+pub const MAX_PERIODS: usize = 24;
+/// Maximum number of installed profiles (MaxChargingProfilesInstalled)
+pub const MAX_PROFILES: usize = 10;
+
+const KEY: &str = "profiles";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Installed {
+    connector_id: i64,
+    profile: ChargingProfile,
+}
+
+/// ChargingProfiles
+/// The charging profiles installed with SetChargingProfile and the current limit they impose
+pub struct ChargingProfiles {
+    installed: Vec<Installed>,
+    storage: Box<dyn Storage>,
+    /// rating of a connector in A, the limit when no profile applies
+    max_current: f64,
+    voltage: f64,
+    phases: usize,
+}
+
+impl ChargingProfiles {
+    /// Loads the profiles persisted in `storage`, TxProfiles are dropped as their transactions
+    /// did not survive the reboot
+    pub fn load(storage: Box<dyn Storage>, max_current: f64, voltage: f64, phases: usize) -> Self {
+        let installed: Vec<Installed> = match storage.load(KEY) {
+            Ok(Some(data)) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::error!("Discarding unreadable charging profiles: {}", e);
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                log::error!("Failed to load the charging profiles: {}", e);
+                Vec::new()
+            }
+        };
+        let installed = installed
+            .into_iter()
+            .filter(|installed| {
+                installed.profile.charging_profile_purpose != ChargingProfilePurposeType::TxProfile
+            })
+            .collect();
+        Self {
+            installed,
+            storage,
+            max_current,
+            voltage,
+            phases,
+        }
+    }
+
+    /// Installs a profile from a SetChargingProfile
+    ///
+    /// # Arguments
+    ///
+    /// * `connector_id` - the connector the profile is for, 0 for the charger as a whole
+    /// * `profile` - the profile, it replaces an installed profile with the same id or the same stack level and purpose
+    /// * `session` - the session on the connector, a TxProfile is only accepted during a transaction
+    ///
+    /// # Returns
+    ///
+    /// ChargingProfileStatus - Rejected for an invalid profile or when it can't be persisted
+    ///
+    pub fn set(
+        &mut self,
+        connector_id: i64,
+        mut profile: ChargingProfile,
+        session: Option<&Session>,
+    ) -> ChargingProfileStatus {
+        if let Err(e) = validate(connector_id, &profile) {
+            log::warn!(
+                "Rejecting charging profile {}: {}",
+                profile.charging_profile_id,
+                e
+            );
+            return ChargingProfileStatus::Rejected;
+        }
+        if profile.charging_profile_purpose == ChargingProfilePurposeType::TxProfile {
+            let Some(session) = session else {
+                log::warn!(
+                    "Rejecting TxProfile without a transaction on {}",
+                    connector_id
+                );
+                return ChargingProfileStatus::Rejected;
+            };
+            if profile.transaction_id.is_some()
+                && session.transaction_id.is_some()
+                && profile.transaction_id != session.transaction_id
+            {
+                log::warn!("Rejecting TxProfile for another transaction");
+                return ChargingProfileStatus::Rejected;
+            }
+            profile.transaction_id = profile.transaction_id.or(session.transaction_id);
+        }
+
+        let mut installed = self.installed.clone();
+        installed.retain(|installed| {
+            installed.profile.charging_profile_id != profile.charging_profile_id
+                && !(installed.connector_id == connector_id
+                    && installed.profile.stack_level == profile.stack_level
+                    && installed.profile.charging_profile_purpose
+                        == profile.charging_profile_purpose)
+        });
+        installed.push(Installed {
+            connector_id,
+            profile,
+        });
+        if installed.len() > MAX_PROFILES {
+            return ChargingProfileStatus::Rejected;
+        }
+        if let Err(e) = self.store(installed) {
+            log::error!("Failed to store the charging profiles: {}", e);
+            return ChargingProfileStatus::Rejected;
+        }
+        ChargingProfileStatus::Accepted
+    }
+
+    /// Removes the profiles matching a ClearChargingProfile, all of them when it has no criteria
+    pub fn clear(&mut self, request: &ClearChargingProfileRequest) -> ClearChargingProfileStatus {
+        let matches = |installed: &Installed| match request.id {
+            Some(id) => installed.profile.charging_profile_id == id,
+            None => {
+                request
+                    .connector_id
+                    .map_or(true, |connector_id| installed.connector_id == connector_id)
+                    && request
+                        .charging_profile_purpose
+                        .as_ref()
+                        .map_or(true, |purpose| {
+                            installed.profile.charging_profile_purpose == *purpose
+                        })
+                    && request.stack_level.map_or(true, |stack_level| {
+                        installed.profile.stack_level as i64 == stack_level
+                    })
+            }
+        };
+        let installed: Vec<Installed> = self
+            .installed
+            .iter()
+            .filter(|installed| !matches(installed))
+            .cloned()
+            .collect();
+        if installed.len() == self.installed.len() {
+            return ClearChargingProfileStatus::Unknown;
+        }
+        if let Err(e) = self.store(installed) {
+            log::error!("Failed to store the charging profiles: {}", e);
+            return ClearChargingProfileStatus::Unknown;
+        }
+        ClearChargingProfileStatus::Accepted
+    }
+
+    /// Removes the TxProfiles of a connector once its transaction has ended
+    pub fn transaction_ended(&mut self, connector_id: i64) {
+        let installed: Vec<Installed> = self
+            .installed
+            .iter()
+            .filter(|installed| {
+                installed.connector_id != connector_id
+                    || installed.profile.charging_profile_purpose
+                        != ChargingProfilePurposeType::TxProfile
+            })
+            .cloned()
+            .collect();
+        if installed.len() == self.installed.len() {
+            return;
+        }
+        if let Err(e) = self.store(installed) {
+            log::error!("Failed to store the charging profiles: {}", e);
+        }
+    }
+
+    /// The current in A a connector may draw at `now`
+    ///
+    /// # Arguments
+    ///
+    /// * `connector_id` - the connector
+    /// * `now` - the instant to evaluate the profiles at
+    /// * `session` - the session on the connector, Relative schedules start with it
+    ///
+    /// # Returns
+    ///
+    /// f64 - the lowest of the ChargePointMaxProfile, the TxProfile (or else TxDefaultProfile)
+    /// with the highest stack level and the rating of the connector
+    ///
+    pub fn limit(&self, connector_id: i64, now: DateTime<Utc>, session: Option<&Session>) -> f64 {
+        let transaction_start = session.map_or(now, |session| session.started_at);
+        self.limit_at(connector_id, now, session, transaction_start)
+    }
+
+    /// The limit of `limit` for a transaction that started at `transaction_start`
+    fn limit_at(
+        &self,
+        connector_id: i64,
+        now: DateTime<Utc>,
+        session: Option<&Session>,
+        transaction_start: DateTime<Utc>,
+    ) -> f64 {
+        let active = |purpose: ChargingProfilePurposeType, connector_id: i64| {
+            self.installed
+                .iter()
+                .filter(|installed| {
+                    installed.connector_id == connector_id
+                        && installed.profile.charging_profile_purpose == purpose
+                        && (purpose != ChargingProfilePurposeType::TxProfile
+                            || session.is_some_and(|session| {
+                                installed.profile.transaction_id.is_none()
+                                    || installed.profile.transaction_id == session.transaction_id
+                            }))
+                })
+                .filter_map(|installed| {
+                    self.profile_limit(&installed.profile, now, transaction_start)
+                        .map(|limit| (installed.profile.stack_level, limit))
+                })
+                .max_by_key(|(stack_level, _)| *stack_level)
+                .map(|(_, limit)| limit)
+        };
+
+        let transaction_limit = active(ChargingProfilePurposeType::TxProfile, connector_id)
+            .or_else(|| active(ChargingProfilePurposeType::TxDefaultProfile, connector_id))
+            .or_else(|| active(ChargingProfilePurposeType::TxDefaultProfile, 0));
+        let charger_limit = active(ChargingProfilePurposeType::ChargePointMaxProfile, 0);
+        [transaction_limit, charger_limit]
+            .into_iter()
+            .flatten()
+            .fold(self.max_current, f64::min)
+    }
+
+    /// The limits of a connector from `start` for `duration` seconds, for a GetCompositeSchedule
+    pub fn composite_schedule(
+        &self,
+        connector_id: i64,
+        start: DateTime<Utc>,
+        duration: i64,
+        unit: ChargingRateUnitType,
+        session: Option<&Session>,
+    ) -> ChargingSchedule {
+        let end = start + Duration::seconds(duration);
+        let transaction_start = session.map_or(start, |session| session.started_at);
+
+        // the limit can only change where a period starts or a schedule or profile begins or ends
+        let mut instants = vec![start];
+        for installed in &self.installed {
+            let profile = &installed.profile;
+            instants.extend(profile.valid_from);
+            instants.extend(profile.valid_to);
+            for schedule_start in self.schedule_starts(profile, start, end, transaction_start) {
+                instants.extend(
+                    profile
+                        .charging_schedule
+                        .charging_schedule_period
+                        .iter()
+                        .map(|period| schedule_start + Duration::seconds(period.start_period)),
+                );
+                instants.extend(
+                    profile
+                        .charging_schedule
+                        .duration
+                        .map(|duration| schedule_start + Duration::seconds(duration)),
+                );
+            }
+        }
+        instants.retain(|instant| *instant >= start && *instant < end);
+        instants.sort();
+        instants.dedup();
+
+        let mut periods: Vec<ChargingSchedulePeriod> = Vec::new();
+        for instant in instants {
+            let current = self.limit_at(connector_id, instant, session, transaction_start);
+            let limit = match unit {
+                ChargingRateUnitType::A => current,
+                ChargingRateUnitType::W => current * self.voltage * self.phases as f64,
+            };
+            if periods.last().is_some_and(|period| period.limit == limit) {
+                continue;
+            }
+            periods.push(ChargingSchedulePeriod {
+                start_period: (instant - start).num_seconds(),
+                limit,
+                number_phases: None,
+            });
+        }
+
+        ChargingSchedule {
+            duration: Some(duration),
+            start_schedule: Some(start),
+            charging_rate_unit: unit,
+            charging_schedule_period: periods,
+            min_charging_rate: None,
+        }
+    }
+
+    /// The limit in A of a single profile at `now`, None when it doesn't apply at that moment
+    fn profile_limit(
+        &self,
+        profile: &ChargingProfile,
+        now: DateTime<Utc>,
+        transaction_start: DateTime<Utc>,
+    ) -> Option<f64> {
+        if profile
+            .valid_from
+            .is_some_and(|valid_from| now < valid_from)
+            || profile.valid_to.is_some_and(|valid_to| now >= valid_to)
+        {
+            return None;
+        }
+        let schedule = &profile.charging_schedule;
+        let schedule_start = self
+            .schedule_starts(profile, now, now, transaction_start)
+            .pop()?;
+        let elapsed = (now - schedule_start).num_seconds();
+        if elapsed < 0
+            || schedule
+                .duration
+                .is_some_and(|duration| elapsed >= duration)
+        {
+            return None;
+        }
+        let period = schedule
+            .charging_schedule_period
+            .iter()
+            .filter(|period| period.start_period <= elapsed)
+            .max_by_key(|period| period.start_period)?;
+        Some(match schedule.charging_rate_unit {
+            ChargingRateUnitType::A => period.limit,
+            ChargingRateUnitType::W => {
+                let phases = period.number_phases.unwrap_or(self.phases as i64).max(1);
+                period.limit / (self.voltage * phases as f64)
+            }
+        })
+    }
+
+    /// The starts of the schedule of a profile that can be running between `from` and `to`,
+    /// more than one for a Recurring profile
+    fn schedule_starts(
+        &self,
+        profile: &ChargingProfile,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        transaction_start: DateTime<Utc>,
+    ) -> Vec<DateTime<Utc>> {
+        let start_schedule = profile.charging_schedule.start_schedule;
+        match profile.charging_profile_kind {
+            ChargingProfileKindType::Absolute => {
+                vec![start_schedule.unwrap_or(transaction_start)]
+            }
+            ChargingProfileKindType::Relative => vec![transaction_start],
+            ChargingProfileKindType::Recurring => {
+                let Some(start_schedule) = start_schedule else {
+                    return Vec::new();
+                };
+                let recurrence = match profile.recurrency_kind {
+                    Some(RecurrencyKindType::Weekly) => Duration::weeks(1),
+                    _ => Duration::days(1),
+                };
+                let first = (from - start_schedule).num_seconds() / recurrence.num_seconds();
+                let last = (to - start_schedule).num_seconds() / recurrence.num_seconds();
+                (first.max(0)..=last.max(0))
+                    .map(|n| start_schedule + recurrence * n as i32)
+                    .collect()
+            }
+        }
+    }
+
+    fn store(&mut self, installed: Vec<Installed>) -> anyhow::Result<()> {
+        self.storage.store(KEY, &serde_json::to_vec(&installed)?)?;
+        self.installed = installed;
+        Ok(())
+    }
+}
+
+/// Checks a profile of a SetChargingProfile against what this charger supports
+fn validate(connector_id: i64, profile: &ChargingProfile) -> anyhow::Result<()> {
+    let schedule = &profile.charging_schedule;
+    let periods = &schedule.charging_schedule_period;
+    if !(0..=1).contains(&connector_id) {
+        anyhow::bail!("unknown connector {}", connector_id);
+    }
+    match profile.charging_profile_purpose {
+        ChargingProfilePurposeType::ChargePointMaxProfile if connector_id != 0 => {
+            anyhow::bail!("a ChargePointMaxProfile can only be set on connector 0")
+        }
+        ChargingProfilePurposeType::TxProfile if connector_id == 0 => {
+            anyhow::bail!("a TxProfile can't be set on connector 0")
+        }
+        _ => {}
+    }
+    if profile.stack_level > MAX_STACK_LEVEL {
+        anyhow::bail!("stack level above {}", MAX_STACK_LEVEL);
+    }
+    if profile.charging_profile_kind == ChargingProfileKindType::Recurring
+        && (profile.recurrency_kind.is_none() || schedule.start_schedule.is_none())
+    {
+        anyhow::bail!("a Recurring profile needs a recurrency kind and start schedule");
+    }
+    if periods.is_empty() || periods.len() > MAX_PERIODS {
+        anyhow::bail!("a schedule needs 1 to {} periods", MAX_PERIODS);
+    }
+    if periods[0].start_period != 0
+        || periods
+            .windows(2)
+            .any(|pair| pair[0].start_period >= pair[1].start_period)
+    {
+        anyhow::bail!("periods have to start at 0 and be in order");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    fn profiles() -> ChargingProfiles {
+        ChargingProfiles::load(Box::new(MemoryStorage::new()), 32.0, 230.0, 3)
+    }
+
+    fn profile(
+        id: i64,
+        purpose: ChargingProfilePurposeType,
+        stack_level: u64,
+        unit: ChargingRateUnitType,
+        periods: &[(i64, f64)],
+    ) -> ChargingProfile {
+        ChargingProfile {
+            charging_profile_id: id,
+            stack_level,
+            charging_profile_purpose: purpose,
+            charging_profile_kind: ChargingProfileKindType::Absolute,
+            charging_schedule: ChargingSchedule {
+                charging_rate_unit: unit,
+                charging_schedule_period: periods
+                    .iter()
+                    .map(|(start_period, limit)| ChargingSchedulePeriod {
+                        start_period: *start_period,
+                        limit: *limit,
+                        number_phases: None,
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn the_highest_stack_level_applies() {
+        let mut profiles = profiles();
+        let now = start();
+        for (id, stack_level, limit) in [(1, 0, 16.0), (2, 2, 10.0), (3, 1, 6.0)] {
+            let mut default = profile(
+                id,
+                ChargingProfilePurposeType::TxDefaultProfile,
+                stack_level,
+                ChargingRateUnitType::A,
+                &[(0, limit)],
+            );
+            default.charging_schedule.start_schedule = Some(now);
+            assert_eq!(
+                profiles.set(1, default, None),
+                ChargingProfileStatus::Accepted
+            );
+        }
+        assert_eq!(profiles.limit(1, now, None), 10.0);
+
+        // a TxProfile overrides the TxDefaultProfiles whatever its stack level
+        let session = Session::new("tag");
+        let mut tx = profile(
+            4,
+            ChargingProfilePurposeType::TxProfile,
+            0,
+            ChargingRateUnitType::A,
+            &[(0, 20.0)],
+        );
+        tx.charging_schedule.start_schedule = Some(now);
+        assert_eq!(
+            profiles.set(1, tx, Some(&session)),
+            ChargingProfileStatus::Accepted
+        );
+        assert_eq!(profiles.limit(1, now, Some(&session)), 20.0);
+        assert_eq!(profiles.limit(1, now, None), 10.0);
+
+        // the ChargePointMaxProfile caps both
+        let mut max = profile(
+            5,
+            ChargingProfilePurposeType::ChargePointMaxProfile,
+            0,
+            ChargingRateUnitType::A,
+            &[(0, 8.0)],
+        );
+        max.charging_schedule.start_schedule = Some(now);
+        assert_eq!(profiles.set(0, max, None), ChargingProfileStatus::Accepted);
+        assert_eq!(profiles.limit(1, now, Some(&session)), 8.0);
+    }
+
+    #[test]
+    fn a_recurring_profile_repeats_every_day() {
+        let mut profiles = profiles();
+        let mut daily = profile(
+            1,
+            ChargingProfilePurposeType::TxDefaultProfile,
+            0,
+            ChargingRateUnitType::A,
+            &[(0, 6.0), (8 * 3600, 16.0)],
+        );
+        daily.charging_profile_kind = ChargingProfileKindType::Recurring;
+        daily.recurrency_kind = Some(RecurrencyKindType::Daily);
+        daily.charging_schedule.start_schedule = Some(start());
+        daily.charging_schedule.duration = Some(20 * 3600);
+        assert_eq!(
+            profiles.set(1, daily, None),
+            ChargingProfileStatus::Accepted
+        );
+
+        let day = Duration::days(3);
+        assert_eq!(
+            profiles.limit(1, start() + day + Duration::hours(1), None),
+            6.0
+        );
+        assert_eq!(
+            profiles.limit(1, start() + day + Duration::hours(9), None),
+            16.0
+        );
+        // past the duration of the schedule only the rating of the connector applies
+        assert_eq!(
+            profiles.limit(1, start() + day + Duration::hours(21), None),
+            32.0
+        );
+    }
+
+    #[test]
+    fn a_relative_profile_starts_with_the_session() {
+        let mut profiles = profiles();
+        let mut relative = profile(
+            1,
+            ChargingProfilePurposeType::TxDefaultProfile,
+            0,
+            ChargingRateUnitType::A,
+            &[(0, 6.0), (600, 16.0)],
+        );
+        relative.charging_profile_kind = ChargingProfileKindType::Relative;
+        assert_eq!(
+            profiles.set(1, relative, None),
+            ChargingProfileStatus::Accepted
+        );
+
+        let mut session = Session::new("tag");
+        session.started_at = start();
+        let session = Some(&session);
+        assert_eq!(
+            profiles.limit(1, start() + Duration::minutes(5), session),
+            6.0
+        );
+        assert_eq!(
+            profiles.limit(1, start() + Duration::minutes(15), session),
+            16.0
+        );
+        // without a session the schedule starts now
+        assert_eq!(
+            profiles.limit(1, start() + Duration::minutes(15), None),
+            6.0
+        );
+    }
+
+    #[test]
+    fn converts_watts_to_amps() {
+        let mut profiles = profiles();
+        let mut watts = profile(
+            1,
+            ChargingProfilePurposeType::TxDefaultProfile,
+            0,
+            ChargingRateUnitType::W,
+            &[(0, 6900.0), (600, 2300.0)],
+        );
+        watts.charging_schedule.start_schedule = Some(start());
+        watts.charging_schedule.charging_schedule_period[1].number_phases = Some(1);
+        assert_eq!(
+            profiles.set(1, watts, None),
+            ChargingProfileStatus::Accepted
+        );
+
+        // 6900 W on 3 phases of 230 V
+        assert_eq!(profiles.limit(1, start(), None), 10.0);
+        // 2300 W on a single phase
+        assert_eq!(
+            profiles.limit(1, start() + Duration::minutes(10), None),
+            10.0
+        );
+    }
+
+    #[test]
+    fn composite_schedule_lists_the_changes_of_the_limit() {
+        let mut profiles = profiles();
+        let mut default = profile(
+            1,
+            ChargingProfilePurposeType::TxDefaultProfile,
+            0,
+            ChargingRateUnitType::A,
+            &[(0, 16.0), (1800, 6.0)],
+        );
+        default.charging_schedule.start_schedule = Some(start());
+        assert_eq!(
+            profiles.set(1, default, None),
+            ChargingProfileStatus::Accepted
+        );
+        let mut higher = profile(
+            2,
+            ChargingProfilePurposeType::TxDefaultProfile,
+            1,
+            ChargingRateUnitType::A,
+            &[(0, 10.0)],
+        );
+        higher.charging_schedule.start_schedule = Some(start() + Duration::minutes(10));
+        higher.charging_schedule.duration = Some(600);
+        assert_eq!(
+            profiles.set(1, higher, None),
+            ChargingProfileStatus::Accepted
+        );
+
+        let schedule = profiles.composite_schedule(1, start(), 3600, ChargingRateUnitType::A, None);
+        let periods: Vec<(i64, f64)> = schedule
+            .charging_schedule_period
+            .iter()
+            .map(|period| (period.start_period, period.limit))
+            .collect();
+        assert_eq!(
+            periods,
+            vec![(0, 16.0), (600, 10.0), (1200, 16.0), (1800, 6.0)]
+        );
+
+        // the same limits in W for 3 phases of 230 V
+        let schedule = profiles.composite_schedule(1, start(), 3600, ChargingRateUnitType::W, None);
+        let limits: Vec<f64> = schedule
+            .charging_schedule_period
+            .iter()
+            .map(|period| period.limit)
+            .collect();
+        assert_eq!(limits, vec![11040.0, 6900.0, 11040.0, 4140.0]);
+    }
+}
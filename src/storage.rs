@@ -0,0 +1,14 @@
+#[cfg(target_os = "espidf")]
+pub mod nvs;
+
+pub mod memory;
+
+/// Storage
+/// Key/value persistence for state that has to survive a reboot
+pub trait Storage: Send {
+    fn load(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    fn store(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+
+    fn remove(&mut self, key: &str) -> anyhow::Result<()>;
+}
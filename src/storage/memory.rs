@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::Storage;
+
+/// MemoryStorage
+/// Storage that is lost on reboot, for running on the host, clones share their values like two
+/// handles of the same NVS namespace
+#[derive(Default, Clone)]
+pub struct MemoryStorage {
+    values: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.into(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> anyhow::Result<()> {
+        self.values.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
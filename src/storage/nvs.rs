@@ -0,0 +1,40 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+use super::Storage;
+
+/// NvsStorage
+/// Storage in a namespace of the default NVS partition, keys are at most 15 characters
+pub struct NvsStorage {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl NvsStorage {
+    pub fn new(partition: EspDefaultNvsPartition, namespace: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, namespace, true)?,
+        })
+    }
+}
+
+impl Storage for NvsStorage {
+    fn load(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(len) = self.nvs.blob_len(key)? else {
+            return Ok(None);
+        };
+        let mut buf = vec![0; len];
+        Ok(self
+            .nvs
+            .get_blob(key, &mut buf)?
+            .map(|value| value.to_vec()))
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.nvs.set_blob(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> anyhow::Result<()> {
+        self.nvs.remove(key)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+#[cfg(target_os = "espidf")]
+pub mod mqtt;
+#[cfg(target_os = "espidf")]
+pub mod websocket;
+
+pub mod loopback;
+
+/// TransportEvent
+/// Something that happened on the connection to the central system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    Connected,
+    Disconnected,
+    Frame(Vec<u8>),
+}
+
+/// Transport
+/// Carries OCPP-J frames to and from the central system
+pub trait Transport: Send {
+    /// Sends a frame, fails when not connected
+    fn send(&mut self, frame: &str) -> anyhow::Result<()>;
+
+    /// Returns the next received frame or connection change without blocking
+    fn receive(&mut self) -> Option<TransportEvent>;
+
+    fn is_connected(&self) -> bool;
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use super::{Transport, TransportEvent};
+
+/// LoopbackTransport
+/// In-memory transport, frames sent on one end are received on the other end of the pair
+pub struct LoopbackTransport {
+    peer: Sender<TransportEvent>,
+    events: Receiver<TransportEvent>,
+    own: Sender<TransportEvent>,
+    connected: Arc<AtomicBool>,
+}
+
+impl LoopbackTransport {
+    /// Creates two connected ends, for instance a charger and a simulated central system
+    pub fn pair() -> (Self, Self) {
+        let (a_sender, a_events) = channel();
+        let (b_sender, b_events) = channel();
+        let connected = Arc::new(AtomicBool::new(true));
+        let _ = a_sender.send(TransportEvent::Connected);
+        let _ = b_sender.send(TransportEvent::Connected);
+        (
+            Self {
+                peer: b_sender.clone(),
+                events: a_events,
+                own: a_sender.clone(),
+                connected: connected.clone(),
+            },
+            Self {
+                peer: a_sender,
+                events: b_events,
+                own: b_sender,
+                connected,
+            },
+        )
+    }
+
+    /// Drops or restores the connection for both ends
+    pub fn set_connected(&self, connected: bool) {
+        if self.connected.swap(connected, Ordering::SeqCst) != connected {
+            let event = if connected {
+                TransportEvent::Connected
+            } else {
+                TransportEvent::Disconnected
+            };
+            let _ = self.own.send(event.clone());
+            let _ = self.peer.send(event);
+        }
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, frame: &str) -> anyhow::Result<()> {
+        if !self.is_connected() {
+            anyhow::bail!("Not connected");
+        }
+        self.peer
+            .send(TransportEvent::Frame(frame.as_bytes().to_vec()))
+            .map_err(|_| anyhow::anyhow!("Peer has gone away"))
+    }
+
+    fn receive(&mut self) -> Option<TransportEvent> {
+        self.events.try_recv().ok()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}
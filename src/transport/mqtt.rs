@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+use esp_idf_svc::mqtt::client::*;
+
+use super::{Transport, TransportEvent};
+use crate::config::{ChargerConfig, MQTTConfig};
+
+/// MqttTransport
+/// Publishes frames on `/charger/{model}/{serial}` and receives them on `/system/{model}/{serial}`
+pub struct MqttTransport {
+    client: EspMqttClient<'static>,
+    topic: String,
+    events: Receiver<TransportEvent>,
+    connected: Arc<AtomicBool>,
+}
+
+impl MqttTransport {
+    /// Connects to the broker and subscribes to the topic of this charger
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - broker url and client id
+    /// * `charger` - model and serial the topics are derived from
+    ///
+    /// # Returns
+    ///
+    /// Result<MqttTransport> - the client, connecting happens in the background
+    ///
+    pub fn new(config: &MQTTConfig, charger: &ChargerConfig) -> anyhow::Result<Self> {
+        let conf = MqttClientConfiguration {
+            client_id: Some(&config.client_id),
+            ..Default::default()
+        };
+
+        let connected = Arc::new(AtomicBool::new(false));
+        let state = connected.clone();
+        let (sender, events) = channel();
+        let broker = config.broker.clone();
+        let mut client = EspMqttClient::new(&config.broker, &conf, move |message_event| {
+            match message_event.as_ref().unwrap() {
+                Event::Connected(_) => {
+                    log::info!("Connected to MQTT {}", broker);
+                    state.store(true, Ordering::SeqCst);
+                    let _ = sender.send(TransportEvent::Connected);
+                }
+                Event::Disconnected => {
+                    log::warn!("Disconnected from MQTT {}", broker);
+                    if state.swap(false, Ordering::SeqCst) {
+                        let _ = sender.send(TransportEvent::Disconnected);
+                    }
+                }
+                Event::Subscribed(id) => log::info!("Subscribed to {} id", id),
+                Event::Received(msg) => {
+                    log::info!("Received message: {}", String::from_utf8_lossy(msg.data()));
+                    let _ = sender.send(TransportEvent::Frame(msg.data().to_vec()));
+                }
+                _ => log::info!("Unhandled event: {:?}", message_event),
+            };
+        })?;
+
+        let topic = format!("/system/{}/{}", &charger.model, &charger.serial);
+        client.subscribe(&topic, QoS::AtLeastOnce)?;
+
+        Ok(Self {
+            client,
+            topic: format!("/charger/{}/{}", &charger.model, &charger.serial),
+            events,
+            connected,
+        })
+    }
+}
+
+impl Transport for MqttTransport {
+    fn send(&mut self, frame: &str) -> anyhow::Result<()> {
+        if !self.is_connected() {
+            anyhow::bail!("Not connected");
+        }
+        self.client
+            .enqueue(&self.topic, QoS::AtMostOnce, false, frame.as_bytes())?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<TransportEvent> {
+        self.events.try_recv().ok()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+
+use embedded_svc::ws::FrameType;
+use esp_idf_svc::ws::client::{
+    EspWebSocketClient, EspWebSocketClientConfig, EspWebSocketTransport, WebSocketEventType,
+};
+
+use super::{Transport, TransportEvent};
+use crate::config::WebSocketConfig;
+use crate::ocpp::SUBPROTOCOL;
+
+/// WebSocketTransport
+/// OCPP-J client connection to `{url}/{chargePointId}`, reconnects and pings on its own
+pub struct WebSocketTransport {
+    client: EspWebSocketClient<'static>,
+    events: Receiver<TransportEvent>,
+    connected: Arc<AtomicBool>,
+}
+
+impl WebSocketTransport {
+    /// Connects to the central system
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - url, ping interval and reconnect timeout
+    /// * `charge_point_id` - identity appended to the url
+    ///
+    /// # Returns
+    ///
+    /// Result<WebSocketTransport> - the client, connecting happens in the background
+    ///
+    pub fn new(config: &WebSocketConfig, charge_point_id: &str) -> anyhow::Result<Self> {
+        let url = format!("{}/{}", config.url.trim_end_matches('/'), charge_point_id);
+        let transport = if url.starts_with("wss://") {
+            EspWebSocketTransport::TransportOverSSL
+        } else {
+            EspWebSocketTransport::TransportOverTCP
+        };
+        let conf = EspWebSocketClientConfig {
+            subprotocol: Some(SUBPROTOCOL),
+            transport,
+            buffer_size: 4096,
+            ping_interval_sec: Duration::from_secs(config.ping_interval),
+            pingpong_timeout_sec: Duration::from_secs(config.ping_interval * 2),
+            reconnect_timeout_ms: Duration::from_secs(config.reconnect_timeout),
+            network_timeout_ms: Duration::from_secs(10),
+            use_global_ca_store: true,
+            ..Default::default()
+        };
+
+        let connected = Arc::new(AtomicBool::new(false));
+        let state = connected.clone();
+        let (sender, events) = channel();
+        let endpoint = url.clone();
+        let client =
+            EspWebSocketClient::new(
+                &url,
+                &conf,
+                Duration::from_secs(10),
+                move |event| match event {
+                    Ok(event) => match event.event_type {
+                        WebSocketEventType::Connected => {
+                            log::info!("Connected to {}", endpoint);
+                            state.store(true, Ordering::SeqCst);
+                            let _ = sender.send(TransportEvent::Connected);
+                        }
+                        WebSocketEventType::Disconnected | WebSocketEventType::Closed => {
+                            log::warn!("Disconnected from {}, reconnecting", endpoint);
+                            if state.swap(false, Ordering::SeqCst) {
+                                let _ = sender.send(TransportEvent::Disconnected);
+                            }
+                        }
+                        WebSocketEventType::Close(reason) => {
+                            log::warn!("{} closed the connection: {:?}", endpoint, reason);
+                        }
+                        WebSocketEventType::Text(text) => {
+                            log::info!("Received message: {}", text);
+                            let _ = sender.send(TransportEvent::Frame(text.as_bytes().to_vec()));
+                        }
+                        WebSocketEventType::Binary(_) => {
+                            log::warn!("Ignoring binary frame, OCPP-J only uses text frames");
+                        }
+                        WebSocketEventType::Ping | WebSocketEventType::Pong => {}
+                    },
+                    Err(e) => log::error!("WebSocket error: {:?}", e),
+                },
+            )?;
+
+        Ok(Self {
+            client,
+            events,
+            connected,
+        })
+    }
+}
+
+impl Transport for WebSocketTransport {
+    /// Sends an OCPP-J frame as a text frame
+    fn send(&mut self, frame: &str) -> anyhow::Result<()> {
+        if !self.is_connected() {
+            anyhow::bail!("Not connected");
+        }
+        self.client.send(FrameType::Text(false), frame.as_bytes())?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<TransportEvent> {
+        self.events.try_recv().ok()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}